@@ -1,16 +1,21 @@
 //! 下载页
 
 use std::{
-    fs::{self, File},
-    io::BufWriter,
+    fs,
     path::{Path, PathBuf},
-    sync::{Arc, RwLock},
-    thread,
+    sync::Arc,
     time::Duration,
 };
 
 use anyhow::Result;
-use bidown::{Progress as ProgressRaw, model::Video, video::Quality};
+use bidown::{
+    CancelToken, Progress as ProgressRaw, Transfer, fetch,
+    id::VideoId,
+    limit::RateLimit,
+    model::Video,
+    package::DATA_FILE,
+    video::{self, Overwrite},
+};
 use log::debug;
 use reqwest::{
     Client,
@@ -20,10 +25,12 @@ use reqwest::{
 };
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{RetryTransientMiddleware, policies::ExponentialBackoff};
-use slint::{ComponentHandle, Weak};
-use tokio::runtime::Runtime;
 
-use crate::{Fetch, MainWindow, utils::show_error};
+use crate::{
+    Fetch,
+    queue::Queue,
+    settings::{Mode, Settings},
+};
 
 //////// fetch ////////
 
@@ -32,7 +39,7 @@ const MIN_RETRY_INTERVAL: Duration = Duration::from_secs(4);
 const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(16);
 
 /// 配置请求头
-fn headers() -> HeaderMap {
+pub fn headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
 
     headers.insert(
@@ -58,7 +65,7 @@ fn headers() -> HeaderMap {
 }
 
 /// 配置客户端
-fn client(headers: HeaderMap) -> Result<ClientWithMiddleware> {
+pub fn client(headers: HeaderMap) -> Result<ClientWithMiddleware> {
     debug!("Building client");
 
     let client = Client::builder().default_headers(headers).build()?;
@@ -68,13 +75,18 @@ fn client(headers: HeaderMap) -> Result<ClientWithMiddleware> {
         .build_with_max_retries(MAX_RETRY);
     let retry_middleware = RetryTransientMiddleware::new_with_policy(retry_policy);
 
-    let client = ClientBuilder::new(client).with(retry_middleware).build();
+    let client = ClientBuilder::new(client)
+        .with(retry_middleware)
+        .with(RateLimit::default())
+        .build();
     Ok(client)
 }
 
-struct Progress {
-    progress: f32,
-    message: String,
+pub struct Progress {
+    pub progress: f32,
+    pub message: String,
+    /// 传输中的进度只更新状态, 不写入日志
+    pub transient: bool,
 }
 
 impl Progress {
@@ -82,141 +94,314 @@ impl Progress {
         Self {
             progress,
             message: message.into(),
+            transient: false,
         }
     }
 
-    fn with_base(progress: f32, message: impl Into<String>, base: f32, max: f32) -> Self {
-        Self::new(base + (max - base) * progress, message)
+    /// 将库的进度事件映射到 `[base, max]` 区间
+    fn from_raw(raw: &ProgressRaw, base: f32, max: f32) -> Self {
+        let progress = base + (max - base) * raw.fraction().unwrap_or_default() as f32;
+        let (message, transient) = match raw {
+            ProgressRaw::Metadata => ("爬取元数据...".into(), false),
+            ProgressRaw::Crawl {
+                current,
+                estimated,
+                id,
+                name,
+            } => (
+                format!("[节点收集: {current}/~{estimated}] 发现节点 {id}: `{name}`"),
+                false,
+            ),
+            ProgressRaw::Download {
+                current,
+                total,
+                id,
+                name,
+                transfer,
+            } => {
+                let done = transfer.done;
+                let message = if done {
+                    format!("[视频下载: {current}/{total}] 下载节点视频 {id}: `{name}`")
+                } else {
+                    format!(
+                        "[视频下载: {current}/{total}] 节点 {id}: {}",
+                        describe_transfer(transfer)
+                    )
+                };
+                (message, !done)
+            }
+            ProgressRaw::Solve { current, total, .. } => {
+                (format!("[求解: {current}/{total}]"), false)
+            }
+        };
+        Self {
+            progress,
+            message,
+            transient,
+        }
     }
 }
 
-async fn fetch_inner<P>(bvid: &str, path: &Path, quality: Quality, mut progress: P) -> Result<()>
+/// 以 MiB 显示字节数
+fn mebibytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024. * 1024.))
+}
+
+/// 传输进度描述, 如 `12.0/48.0 MiB, 2.0 MiB/s, 剩余 18 秒`
+fn describe_transfer(transfer: &Transfer) -> String {
+    let mut text = mebibytes(transfer.downloaded);
+    if let Some(total) = transfer.total {
+        text = format!("{text}/{}", mebibytes(total));
+    }
+    text += &format!(", {}/s", mebibytes(transfer.speed as u64));
+    if let Some(eta) = transfer.eta {
+        text += &format!(", 剩余 {} 秒", eta.as_secs());
+    }
+    text
+}
+
+/// 执行下载任务
+///
+/// 已知下载目录 `dir` 中有 `data.json` 且不覆盖时跳过爬取 (继续下载);
+/// 只爬取时爬取完整剧情树并保存描述; 否则边爬取边下载.
+/// 已知 `dir` 时总是使用该目录, 否则按命名模板在 `root` 下确定, 确定后调用 `locate`.
+#[allow(clippy::too_many_arguments)]
+pub async fn execute<P, L>(
+    client: &ClientWithMiddleware,
+    input: &str,
+    root: &Path,
+    dir: Option<&Path>,
+    settings: &Settings,
+    mut progress: P,
+    locate: L,
+    cancel: &CancelToken,
+) -> Result<()>
 where
     P: FnMut(Progress),
+    L: FnOnce(&Path),
 {
-    progress(Progress::new(
-        0.,
-        format!("开始下载视频 `{bvid}`, 质量 `{quality:?}`"),
-    ));
+    let resume = settings.download.overwrite == Overwrite::Resume;
+    if let Some(dir) = dir
+        && resume
+        && dir.join(DATA_FILE).exists()
+    {
+        progress(Progress::new(0.2, "读取已保存的视频信息..."));
+        let video = Video::from_file(&dir.join(DATA_FILE))?;
+        locate(dir);
+        return download(client, &video, dir, settings, progress, cancel).await;
+    }
 
-    progress(Progress::new(0., "启动客户端..."));
-    let client = client(headers())?;
+    progress(Progress::new(0., format!("解析视频标识 `{input}`...")));
+    let bvid = cancel
+        .run(VideoId::resolve(client, input))
+        .await
+        .ok_or(fetch::Error::Cancelled)??;
+
+    if settings.mode == Mode::CrawlOnly {
+        let dir = crawl(client, &bvid, root, dir, settings, &mut progress, cancel).await?;
+        locate(&dir);
+        progress(Progress::new(
+            1.,
+            format!("完成爬取! 位置: `{}`", dir.to_string_lossy()),
+        ));
+        return Ok(());
+    }
+    pipeline(client, &bvid, root, dir, settings, progress, locate, cancel).await
+}
 
-    progress(Progress::new(0.05, "爬取剧情树..."));
-    let video = Video::fetch(
-        &client,
+/// 视频目录, 未指定时按命名模板在 `root` 下确定
+fn video_dir(root: &Path, dir: Option<&Path>, settings: &Settings, video: &Video) -> PathBuf {
+    dir.map_or_else(|| root.join(settings.dir_name(video)), Path::to_path_buf)
+}
+
+/// 爬取完整剧情树, 确定目录并保存描述
+async fn crawl<P>(
+    client: &ClientWithMiddleware,
+    bvid: &VideoId,
+    root: &Path,
+    dir: Option<&Path>,
+    settings: &Settings,
+    mut progress: P,
+    cancel: &CancelToken,
+) -> Result<PathBuf>
+where
+    P: FnMut(Progress),
+{
+    progress(Progress::new(0.05, format!("爬取剧情树 `{bvid}`...")));
+    let video = Video::fetch_with_cancel(
+        client,
         bvid,
-        |ProgressRaw {
-             current, id, name, ..
-         }| {
-            progress(Progress::new(
-                0.05,
-                format!("[节点收集: {current}] 发现节点 {id}: `{name}`"),
-            ))
-        },
+        |raw| progress(Progress::from_raw(&raw, 0.05, 1.)),
+        cancel,
     )
     .await?;
 
-    progress(Progress::new(0.2, "保存视频信息到本地..."));
-    fs::create_dir_all(path)?;
-    let data_path = path.join("data.json");
-    serde_json::to_writer_pretty(BufWriter::new(File::create(data_path)?), &video)?;
-
-    progress(Progress::new(0.25, "下载节点视频..."));
-    let video_path = path.join("video");
-    fs::create_dir(&video_path)?;
-    video
-        .download(
-            &client,
-            &video_path,
-            quality,
-            |ProgressRaw {
-                 current,
-                 total,
-                 id,
-                 name,
-             }| {
-                progress(Progress::with_base(
-                    current as f32 / total as f32,
-                    format!("[视频下载: {current}/{total}] 下载节点视频 {id}: `{name}`"),
-                    0.2,
-                    1.,
-                ))
-            },
-        )
-        .await?;
-
+    let dir = video_dir(root, dir, settings, &video);
     progress(Progress::new(
-        0.,
-        format!("完成下载! 位置: `{}`", path.to_string_lossy()),
+        1.,
+        format!("保存视频信息到 `{}`...", dir.to_string_lossy()),
     ));
-    Ok(())
+    fs::create_dir_all(&dir)?;
+    video.to_file(&dir.join(DATA_FILE))?;
+    Ok(dir)
 }
 
-//////// bind ////////
+/// 边爬取边下载节点视频, 完成后保存描述并生成离线播放器
+///
+/// 节点下载失败时继续爬取, 完成后报告失败的节点, 可从描述文件继续下载重试;
+/// 取消或出错时, 若剧情树已爬取完整也保存描述.
+#[allow(clippy::too_many_arguments)]
+async fn pipeline<P, L>(
+    client: &ClientWithMiddleware,
+    bvid: &VideoId,
+    root: &Path,
+    dir: Option<&Path>,
+    settings: &Settings,
+    mut progress: P,
+    locate: L,
+    cancel: &CancelToken,
+) -> Result<()>
+where
+    P: FnMut(Progress),
+    L: FnOnce(&Path),
+{
+    progress(Progress::new(0.05, format!("爬取元数据 `{bvid}`...")));
+    let (mut video, nodes) = cancel
+        .run(Video::fetch_stream(client, bvid))
+        .await
+        .ok_or(fetch::Error::Cancelled)??;
 
-const QUALITY: Quality = Quality::High;
+    let dir = video_dir(root, dir, settings, &video);
+    fs::create_dir_all(&dir)?;
+    locate(&dir);
 
-#[derive(Debug, Default)]
-struct ProgressData {
-    progress: f32,
-    messages: Vec<String>,
+    let options = &settings.download;
+    progress(Progress::new(
+        0.1,
+        format!(
+            "边爬取边下载节点视频, 质量 `{:?}`, 并发 {}...",
+            options.quality, options.concurrency
+        ),
+    ));
+    // 进度条跟随下载进度, 爬取进度只更新消息
+    let mut last = 0.1;
+    let result = video
+        .download_stream(
+            client,
+            nodes,
+            &dir,
+            options,
+            |raw| {
+                let mut mapped = Progress::from_raw(&raw, 0.1, 1.);
+                if matches!(raw, ProgressRaw::Crawl { .. }) {
+                    mapped.progress = last;
+                } else {
+                    last = mapped.progress;
+                }
+                progress(mapped)
+            },
+            cancel,
+        )
+        .await;
+
+    if result.is_ok() || video.graph.is_complete() {
+        progress(Progress::new(
+            1.,
+            format!("保存视频信息到 `{}`...", dir.to_string_lossy()),
+        ));
+        video.to_file(&dir.join(DATA_FILE))?;
+    }
+    let failed = result?;
+
+    progress(Progress::new(1., "生成离线播放器..."));
+    video.export_player(&dir)?;
+
+    let message = if failed.is_empty() {
+        format!("完成下载! 位置: `{}`", dir.to_string_lossy())
+    } else {
+        format!(
+            "下载完成, 但 {} 个节点失败: {failed:?}, 可继续下载重试. 位置: `{}`",
+            failed.len(),
+            dir.to_string_lossy()
+        )
+    };
+    progress(Progress::new(1., message));
+    Ok(())
 }
 
-impl ProgressData {
-    // fn reset(&mut self) {
-    //     *self = Self::default();
-    // }
-
-    fn update(&mut self, progress: Progress) {
-        let Progress { progress, message } = progress;
-        self.progress = progress;
-        self.messages.push(message);
+/// 按设置下载节点视频和附加资源, 生成离线播放器
+async fn download<P>(
+    client: &ClientWithMiddleware,
+    video: &Video,
+    dir: &Path,
+    settings: &Settings,
+    mut progress: P,
+    cancel: &CancelToken,
+) -> Result<()>
+where
+    P: FnMut(Progress),
+{
+    if settings.mode == Mode::CrawlOnly {
+        progress(Progress::new(
+            1.,
+            format!("完成爬取! 位置: `{}`", dir.to_string_lossy()),
+        ));
+        return Ok(());
     }
 
-    fn messages(&self) -> String {
-        self.messages.join("\n")
-    }
-}
+    let options = &settings.download;
+    progress(Progress::new(
+        0.25,
+        format!(
+            "下载节点视频, 质量 `{:?}`, 并发 {}...",
+            options.quality, options.concurrency
+        ),
+    ));
+    video
+        .download_with(
+            client,
+            dir,
+            options,
+            |raw| progress(Progress::from_raw(&raw, 0.25, 1.)),
+            cancel,
+        )
+        .await?;
 
-pub fn bind_fetch<'a>(fetch: Fetch<'a>, ui: Weak<MainWindow>) {
-    fetch.on_fetch(move |bvid, path| {
-        ui.upgrade_in_event_loop(|ui| ui.global::<Fetch>().set_is_fetching(true))
-            .unwrap();
-
-        let bvid = bvid.to_string();
-        let path = PathBuf::from(path.as_str()).join(&bvid);
-
-        let progress = {
-            let ui = ui.clone();
-            let progress: Arc<RwLock<ProgressData>> = Arc::default();
-            move |p| {
-                progress.write().unwrap().update(p);
-                let progress = progress.clone();
-                ui.upgrade_in_event_loop(move |ui| {
-                    debug!("Updating fetching progress...");
-                    let progress = progress.read().unwrap();
-                    let fetch = ui.global::<Fetch>();
-                    fetch.set_progress(progress.progress);
-                    fetch.set_log(progress.messages().into());
-                })
-                .unwrap();
-            }
-        };
+    progress(Progress::new(1., "生成离线播放器..."));
+    video.export_player(dir)?;
 
-        let _ = thread::spawn({
-            let ui = ui.clone();
-            move || {
-                debug!("Fetching thread spawned");
-                let task = fetch_inner(&bvid, &path, QUALITY, progress);
+    progress(Progress::new(
+        1.,
+        format!("完成下载! 位置: `{}`", dir.to_string_lossy()),
+    ));
+    Ok(())
+}
 
-                let runtime = Runtime::new().expect("Failed to create tokio runtime");
-                let result = runtime.block_on(task);
+/// 是否为取消令牌引起的错误
+pub fn is_cancelled(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref(), Some(fetch::Error::Cancelled))
+        || matches!(e.downcast_ref(), Some(video::Error::Cancelled))
+        || e.downcast_ref().is_some_and(bidown::Error::is_cancelled)
+}
 
-                let _ = result.inspect_err(show_error);
-                ui.upgrade_in_event_loop(|ui| ui.global::<Fetch>().set_is_fetching(false))
-                    .unwrap();
-            }
-        });
+//////// bind ////////
+
+pub fn bind_fetch<'a>(fetch: Fetch<'a>, queue: Arc<Queue>) {
+    fetch.on_enqueue({
+        let queue = queue.clone();
+        move |inputs, path| queue.enqueue(&inputs, Path::new(path.as_str()))
+    });
+    fetch.on_pause({
+        let queue = queue.clone();
+        move |id| queue.pause(id as u64)
+    });
+    fetch.on_resume({
+        let queue = queue.clone();
+        move |id| queue.resume(id as u64)
+    });
+    fetch.on_remove({
+        let queue = queue.clone();
+        move |id| queue.remove(id as u64)
     });
+    fetch.on_select(move |id| queue.select(id as u64));
 }
@@ -0,0 +1,289 @@
+//! 剧情图页
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use anyhow::Result;
+use bidown::{
+    layout::{Layout, NODE_HEIGHT, NODE_WIDTH, Route},
+    model::{Node, NodeConfig, Video},
+};
+use log::debug;
+use serde::Deserialize;
+use slint::{ComponentHandle, ModelRc, VecModel, Weak};
+
+use crate::{GraphNode, MainWindow, Viewer, utils::show_error};
+
+//////// state ////////
+
+/// 求解结果中的一步, 见 `data.sln.json`
+#[derive(Debug, Deserialize)]
+struct SolvedStep {
+    id: usize,
+    name: String,
+    /// 进入此节点的选项
+    edge: Option<usize>,
+    choice: Option<String>,
+}
+
+/// 已加载的剧情图
+#[derive(Debug, Default)]
+struct State {
+    video: Option<Video>,
+    layout: Layout,
+    /// 节点到求解路径 (倒序) 的映射
+    solution: HashMap<usize, Vec<SolvedStep>>,
+    keyword: String,
+    selected: Option<usize>,
+}
+
+/// 选项, 条件与修改的说明
+fn detail(node: &Node) -> String {
+    let mut out = format!("[{}] {}", node.id, node.name);
+    match &node.config {
+        NodeConfig::Choice {
+            duration: Some(duration),
+            ..
+        } => {
+            let _ = write!(out, "\n限时 {} 秒", *duration as f64 / 1000.);
+        }
+        NodeConfig::Jump { .. } => out.push_str("\n自动跳转"),
+        NodeConfig::Leaf => out.push_str("\n结局"),
+        _ => {}
+    }
+    for choice in node.config.choices() {
+        let _ = write!(
+            out,
+            "\n\n({}) {} → {}",
+            choice.id, choice.name, choice.target
+        );
+        for condition in &choice.conditions {
+            let _ = write!(out, "\n  条件: {condition}");
+        }
+        for change in &choice.changes {
+            let _ = write!(out, "\n  修改: {change}");
+        }
+    }
+    out
+}
+
+/// SVG 路径命令
+fn commands<'a>(routes: impl Iterator<Item = &'a Route>) -> String {
+    let mut out = String::new();
+    for route in routes {
+        for (k, (x, y)) in route.points.iter().enumerate() {
+            let op = if k == 0 { 'M' } else { 'L' };
+            let _ = write!(out, "{op} {x:.1} {y:.1} ");
+        }
+    }
+    out
+}
+
+/// 路径描述, 如 `N1 →[C1] N2`
+fn describe(steps: &[SolvedStep]) -> String {
+    steps
+        .iter()
+        .rev()
+        .map(|s| match &s.choice {
+            Some(choice) => format!(" →[{choice}] {}", s.name),
+            None => s.name.clone(),
+        })
+        .collect()
+}
+
+impl State {
+    fn matches(&self, node: &Node) -> bool {
+        let keyword = self.keyword.trim();
+        !keyword.is_empty() && (node.name.contains(keyword) || node.id.to_string() == keyword)
+    }
+
+    fn path(&self) -> &[SolvedStep] {
+        self.selected
+            .and_then(|id| self.solution.get(&id))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn nodes(&self) -> Vec<GraphNode> {
+        let Some(video) = &self.video else {
+            return Vec::new();
+        };
+        let nodes = video.graph.nodes_map();
+        let on_path: HashSet<usize> = self.path().iter().map(|s| s.id).collect();
+
+        self.layout
+            .nodes
+            .iter()
+            .filter_map(|p| {
+                let node = nodes.get(&p.id)?;
+                Some(GraphNode {
+                    id: node.id as i32,
+                    name: node.name.clone().into(),
+                    x: p.x as f32,
+                    y: p.y as f32,
+                    ending: node.is_leaf(),
+                    matched: self.matches(node),
+                    highlighted: on_path.contains(&node.id),
+                    detail: detail(node).into(),
+                })
+            })
+            .collect()
+    }
+
+    fn apply(&self, viewer: &Viewer) {
+        let edges = &self.layout.edges;
+        let choices: HashSet<usize> = self.path().iter().filter_map(|s| s.edge).collect();
+
+        viewer.set_nodes(ModelRc::new(VecModel::from(self.nodes())));
+        viewer.set_edges(commands(edges.iter().filter(|e| !e.back)).into());
+        viewer.set_back_edges(commands(edges.iter().filter(|e| e.back)).into());
+        viewer
+            .set_path_edges(commands(edges.iter().filter(|e| choices.contains(&e.choice))).into());
+        viewer.set_width(self.layout.width as f32);
+        viewer.set_height(self.layout.height as f32);
+        viewer.set_node_width(NODE_WIDTH as f32);
+        viewer.set_node_height(NODE_HEIGHT as f32);
+    }
+}
+
+//////// load ////////
+
+fn load_inner(path: &Path) -> Result<(State, String)> {
+    let video = Video::from_file(path)?;
+    let layout = video.graph.layout();
+
+    // 求解页的结果写在描述文件旁边
+    let out = path.with_extension("sln.json");
+    let solution = match fs::read(&out) {
+        Ok(bytes) => {
+            let paths: Vec<Vec<SolvedStep>> = serde_json::from_slice(&bytes)?;
+            paths
+                .into_iter()
+                .filter_map(|p| Some((p.first()?.id, p)))
+                .collect()
+        }
+        Err(e) => {
+            debug!(
+                "No solution loaded from at `{}`: {e}",
+                out.to_string_lossy()
+            );
+            HashMap::new()
+        }
+    };
+
+    let status = if solution.is_empty() {
+        format!(
+            "已加载 `{}`, 共 {} 个节点; 未找到求解结果, 可在求解页求解后重新加载",
+            video.name,
+            video.graph.nodes.len()
+        )
+    } else {
+        format!(
+            "已加载 `{}`, 共 {} 个节点, {} 个节点可达; 点击节点高亮求解路径",
+            video.name,
+            video.graph.nodes.len(),
+            solution.len()
+        )
+    };
+
+    let state = State {
+        video: Some(video),
+        layout,
+        solution,
+        ..Default::default()
+    };
+    Ok((state, status))
+}
+
+//////// bind ////////
+
+pub fn bind_graph<'a>(viewer: Viewer<'a>, ui: Weak<MainWindow>) {
+    let state = Arc::new(Mutex::new(State::default()));
+
+    viewer.on_load({
+        let state = state.clone();
+        let ui = ui.clone();
+        move |path| {
+            ui.upgrade_in_event_loop(|ui| {
+                let viewer = ui.global::<Viewer>();
+                viewer.set_is_loading(true);
+                viewer.set_status("加载中...".into());
+            })
+            .unwrap();
+
+            let path = PathBuf::from(path.as_str());
+            let state = state.clone();
+            let ui = ui.clone();
+            let _ = thread::spawn(move || {
+                debug!("Loading graph at `{}`", path.to_string_lossy());
+                let result = load_inner(&path).inspect_err(show_error);
+
+                ui.upgrade_in_event_loop(move |ui| {
+                    let viewer = ui.global::<Viewer>();
+                    match result {
+                        Ok((loaded, status)) => {
+                            let mut state = state.lock().unwrap();
+                            *state = loaded;
+                            state.apply(&viewer);
+                            viewer.set_status(status.into());
+                        }
+                        Err(e) => viewer.set_status(format!("加载失败: {e}").into()),
+                    }
+                    viewer.set_is_loading(false);
+                })
+                .unwrap();
+            });
+        }
+    });
+
+    viewer.on_search({
+        let state = state.clone();
+        let ui = ui.clone();
+        move |keyword| {
+            let Some(ui) = ui.upgrade() else {
+                return -1;
+            };
+            let mut state = state.lock().unwrap();
+            state.keyword = keyword.to_string();
+            state.apply(&ui.global::<Viewer>());
+
+            let Some(video) = &state.video else {
+                return -1;
+            };
+            let nodes = video.graph.nodes_map();
+            state
+                .layout
+                .nodes
+                .iter()
+                .position(|p| nodes.get(&p.id).is_some_and(|n| state.matches(n)))
+                .map_or(-1, |k| k as i32)
+        }
+    });
+
+    viewer.on_select(move |id| {
+        let Some(ui) = ui.upgrade() else {
+            return;
+        };
+        let viewer = ui.global::<Viewer>();
+        let mut state = state.lock().unwrap();
+        let id = id as usize;
+
+        if state.selected == Some(id) {
+            state.selected = None;
+            viewer.set_status("已取消高亮".into());
+        } else if let Some(steps) = state.solution.get(&id) {
+            viewer.set_status(format!("路径: {}", describe(steps)).into());
+            state.selected = Some(id);
+        } else {
+            state.selected = None;
+            viewer.set_status(format!("节点 {id} 没有求解路径").into());
+        }
+        state.apply(&viewer);
+    });
+}
@@ -0,0 +1,207 @@
+//! 资料库页
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+};
+
+use anyhow::Result;
+use bidown::{
+    model::Video,
+    package::{Assets, DATA_FILE, VIDEO_DIR},
+    video::{Checksums, Verification},
+};
+use log::{debug, warn};
+use slint::{ComponentHandle, Image, Model, ModelRc, VecModel, Weak};
+
+use crate::{Library, LibraryItem, MainWindow, queue::Queue, utils::show_error};
+
+//////// scan ////////
+
+/// 已下载的视频 (可以跨线程传递)
+struct Entry {
+    bvid: String,
+    title: String,
+    author: String,
+    cover: Option<PathBuf>,
+    nodes: usize,
+    /// 文件存在的节点数, 含没有完整性记录的节点
+    downloaded: usize,
+    /// 文件存在但没有完整性记录的节点数 (如旧版本的下载)
+    unrecorded: usize,
+    dir: PathBuf,
+}
+
+impl From<Entry> for LibraryItem {
+    fn from(value: Entry) -> Self {
+        let Entry {
+            bvid,
+            title,
+            author,
+            cover,
+            nodes,
+            downloaded,
+            unrecorded,
+            dir,
+        } = value;
+        let cover = cover
+            .and_then(|p| Image::load_from_path(&p).ok())
+            .unwrap_or_default();
+        Self {
+            bvid: bvid.into(),
+            title: title.into(),
+            author: author.into(),
+            cover,
+            nodes: nodes as i32,
+            downloaded: downloaded as i32,
+            path: dir.to_string_lossy().as_ref().into(),
+            data: dir.join(DATA_FILE).to_string_lossy().as_ref().into(),
+            status: match unrecorded {
+                0 => "".into(),
+                n => format!("{n} 个节点没有完整性记录, 继续下载时与服务器核对").into(),
+            },
+        }
+    }
+}
+
+/// 读取一个视频目录, 不含 `data.json` 时返回 `None`
+fn load_entry(dir: &Path) -> Result<Option<Entry>> {
+    let data = dir.join(DATA_FILE);
+    if !data.is_file() {
+        return Ok(None);
+    }
+    let video = Video::from_file(&data)?;
+    let assets = Assets::scan(dir, &video)?;
+
+    let videos = dir.join(VIDEO_DIR);
+    let checksums = Checksums::load(&videos)?;
+    let (downloaded, unrecorded) = video
+        .graph
+        .nodes
+        .iter()
+        .filter(|n| videos.join(format!("{}.mp4", n.id)).is_file())
+        .fold((0, 0), |(downloaded, unrecorded), n| {
+            let recorded = checksums.0.contains_key(&n.id);
+            (downloaded + 1, unrecorded + usize::from(!recorded))
+        });
+
+    Ok(Some(Entry {
+        bvid: video.id.bvid().to_string(),
+        title: video.name,
+        author: video.author,
+        cover: assets.cover.map(|c| dir.join(c)),
+        nodes: video.graph.nodes.len(),
+        downloaded,
+        unrecorded,
+        dir: dir.to_path_buf(),
+    }))
+}
+
+/// 扫描下载根目录下的视频目录, 无法读取的目录只记录警告
+fn scan(root: &Path) -> Result<Vec<Entry>> {
+    debug!("Scanning library at `{}`", root.to_string_lossy());
+    let mut entries = Vec::new();
+    for dir in fs::read_dir(root)? {
+        let dir = dir?.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        match load_entry(&dir) {
+            Ok(Some(entry)) => entries.push(entry),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to load `{}`: {e}", dir.to_string_lossy()),
+        }
+    }
+    entries.sort_by(|a, b| a.title.cmp(&b.title));
+    Ok(entries)
+}
+
+/// 校验报告描述
+fn describe(report: &Verification) -> String {
+    if report.is_ok() && report.unrecorded.is_empty() {
+        return "校验通过".into();
+    }
+    format!(
+        "缺失 {}, 截断 {}, 损坏 {}, 未记录 {}",
+        report.missing.len(),
+        report.truncated.len(),
+        report.corrupt.len(),
+        report.unrecorded.len()
+    )
+}
+
+fn verify_inner(dir: &Path) -> Result<Verification> {
+    let video = Video::from_file(&dir.join(DATA_FILE))?;
+    Ok(video.verify_download(&dir.join(VIDEO_DIR))?)
+}
+
+/// 更新指定目录对应条目的状态
+fn set_status(ui: &MainWindow, path: &str, status: String) {
+    let items = ui.global::<Library>().get_items();
+    for k in 0..items.row_count() {
+        if let Some(mut item) = items.row_data(k)
+            && item.path.as_str() == path
+        {
+            item.status = status.into();
+            items.set_row_data(k, item);
+            return;
+        }
+    }
+}
+
+//////// bind ////////
+
+pub fn bind_library<'a>(library: Library<'a>, ui: Weak<MainWindow>, queue: Arc<Queue>) {
+    library.on_scan({
+        let ui = ui.clone();
+        move |root| {
+            ui.upgrade_in_event_loop(|ui| ui.global::<Library>().set_is_scanning(true))
+                .unwrap();
+
+            let root = PathBuf::from(root.as_str());
+            let ui = ui.clone();
+            let _ = thread::spawn(move || {
+                let entries = scan(&root).inspect_err(show_error).unwrap_or_default();
+                ui.upgrade_in_event_loop(move |ui| {
+                    let library = ui.global::<Library>();
+                    let items: Vec<LibraryItem> = entries.into_iter().map(Into::into).collect();
+                    library.set_items(ModelRc::new(VecModel::from(items)));
+                    library.set_is_scanning(false);
+                })
+                .unwrap();
+            });
+        }
+    });
+
+    library.on_open_folder(|path| {
+        debug!("Opening folder `{path}`");
+        if let Err(e) = open::that_detached(path.as_str()) {
+            show_error(&e.into());
+        }
+    });
+
+    library.on_verify({
+        let ui = ui.clone();
+        move |path| {
+            let path = path.to_string();
+            let ui = ui.clone();
+            let _ = thread::spawn(move || {
+                let status = match verify_inner(Path::new(&path)) {
+                    Ok(report) => describe(&report),
+                    Err(e) => format!("校验失败: {e}"),
+                };
+                ui.upgrade_in_event_loop(move |ui| set_status(&ui, &path, status))
+                    .unwrap();
+            });
+        }
+    });
+
+    library.on_redownload(move |bvid, path| {
+        queue.enqueue_dir(&bvid, Path::new(path.as_str()));
+        if let Some(ui) = ui.upgrade() {
+            set_status(&ui, &path, "已加入下载队列".into());
+        }
+    });
+}
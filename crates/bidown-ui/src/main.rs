@@ -4,28 +4,43 @@
 
 slint::include_modules!();
 
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
 use anyhow::Result;
 use log::{LevelFilter, debug};
 use rfd::FileDialog;
 use slint::ComponentHandle;
 
-use crate::fetch::bind_fetch;
+use crate::{
+    fetch::bind_fetch,
+    graph::bind_graph,
+    library::bind_library,
+    queue::Queue,
+    settings::{Settings, bind_settings},
+    solve::bind_solve,
+};
 
 mod fetch;
+mod graph;
+mod library;
+mod queue;
+mod settings;
+mod solve;
 mod utils;
 
 //////// log ////////
 
-const LOG_DIR: &str = "./log";
 const LOG_LEVEL: LevelFilter = LevelFilter::Info;
 
-fn init_log() -> Result<()> {
+fn init_log(root: &Path) -> Result<()> {
     let time = chrono::Local::now();
     let date = time.format("%Y-%m-%d").to_string();
 
-    let dir = PathBuf::from(LOG_DIR).join(date);
+    let dir = root.join(date);
     fs::create_dir_all(&dir)?;
 
     let date = time.format("%H-%M-%S").to_string();
@@ -61,21 +76,46 @@ fn bind_utils(utils: Utility<'_>) {
         let path = result.and_then(|p| p.to_str().map(str::to_string));
         path.unwrap_or_default().into()
     });
+
+    utils.on_pick_file(|filter| {
+        debug!("Open file picker, filter=`{filter}`");
+        let mut dialog = FileDialog::new().set_title("选择文件");
+        if !filter.is_empty() {
+            dialog = dialog.add_filter(filter.as_str(), &[filter.as_str()]);
+        }
+        let result = dialog.pick_file();
+        debug!("User pick file result: `{result:?}`");
+        let path = result.and_then(|p| p.to_str().map(str::to_string));
+        path.unwrap_or_default().into()
+    });
 }
 
-fn open() -> Result<MainWindow> {
+fn open(settings: Settings) -> Result<MainWindow> {
     debug!("Initializing UI...");
     let ui = MainWindow::new()?;
 
     bind_utils(ui.global::<Utility>());
-    bind_fetch(ui.global::<Fetch>(), ui.as_weak());
+    let output = settings.output.clone();
+    let settings = Arc::new(Mutex::new(settings));
+    bind_settings(ui.global::<Options>(), ui.as_weak(), settings.clone());
+    let queue = Queue::new(ui.as_weak(), settings)?;
+    bind_fetch(ui.global::<Fetch>(), queue.clone());
+    bind_library(ui.global::<Library>(), ui.as_weak(), queue);
+    bind_solve(ui.global::<Solve>(), ui.as_weak());
+    bind_graph(ui.global::<Viewer>(), ui.as_weak());
+
+    // 启动时扫描上次的下载目录
+    if !output.is_empty() {
+        ui.global::<Library>().invoke_scan(output.into());
+    }
 
     debug!("UI initialized");
     Ok(ui)
 }
 
 fn main() -> Result<()> {
-    init_log()?;
-    open()?.run()?;
+    let settings = Settings::load();
+    init_log(&settings.log_dir())?;
+    open(settings)?.run()?;
     Ok(())
 }
@@ -0,0 +1,431 @@
+//! 下载队列
+//!
+//! 所有任务在同一个 tokio 运行时上执行, 共享同一个客户端 (及其限流).
+//! 队列在每次状态变化后写入配置目录下的 [`QUEUE_FILE`], 重启后恢复.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use bidown::{
+    CancelToken,
+    event::{Event, observe},
+};
+use log::{debug, info, warn};
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+use slint::{ComponentHandle, Model, ModelRc, VecModel, Weak};
+use tokio::runtime::Runtime;
+
+use crate::{
+    Fetch, MainWindow, TaskItem, TaskState,
+    fetch::{Progress, client, execute, headers, is_cancelled},
+    settings::{Settings, config_dir, write_config},
+};
+
+//////// task ////////
+
+/// 队列持久化文件
+const QUEUE_FILE: &str = "queue.json";
+/// 同时执行的任务数
+const MAX_RUNNING: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Queued,
+    Running,
+    Paused,
+    Failed,
+    Done,
+}
+
+impl From<Status> for TaskState {
+    fn from(value: Status) -> Self {
+        match value {
+            Status::Queued => Self::Queued,
+            Status::Running => Self::Running,
+            Status::Paused => Self::Paused,
+            Status::Failed => Self::Failed,
+            Status::Done => Self::Done,
+        }
+    }
+}
+
+/// 下载任务
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Task {
+    id: u64,
+    /// 用户输入的视频标识
+    input: String,
+    /// 下载根目录
+    root: PathBuf,
+    /// 视频目录, 爬取完成后确定
+    #[serde(default)]
+    dir: Option<PathBuf>,
+    status: Status,
+    progress: f32,
+    message: String,
+    #[serde(skip)]
+    log: Vec<String>,
+    /// 执行次数, 用于忽略已暂停或移除的旧执行的回报
+    #[serde(skip)]
+    run: u64,
+}
+
+impl From<&Task> for TaskItem {
+    fn from(value: &Task) -> Self {
+        Self {
+            id: value.id as i32,
+            input: value.input.as_str().into(),
+            path: value
+                .dir
+                .as_ref()
+                .unwrap_or(&value.root)
+                .to_string_lossy()
+                .as_ref()
+                .into(),
+            state: value.status.into(),
+            progress: value.progress,
+            message: value.message.as_str().into(),
+        }
+    }
+}
+
+//////// queue ////////
+
+#[derive(Debug, Default)]
+struct Inner {
+    tasks: Vec<Task>,
+    /// 执行中任务的取消令牌
+    tokens: HashMap<u64, CancelToken>,
+    selected: Option<u64>,
+}
+
+impl Inner {
+    fn task(&mut self, id: u64) -> Option<&mut Task> {
+        self.tasks.iter_mut().find(|t| t.id == id)
+    }
+
+    fn push(&mut self, input: &str, root: &Path, dir: Option<PathBuf>) {
+        let id = self.tasks.iter().map(|t| t.id + 1).max().unwrap_or(0);
+        debug!("Enqueue task {id}: `{input}`");
+        self.tasks.push(Task {
+            id,
+            input: input.to_string(),
+            root: root.to_path_buf(),
+            dir,
+            status: Status::Queued,
+            progress: 0.,
+            message: "等待中".into(),
+            log: Vec::new(),
+            run: 0,
+        });
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.tasks)
+            .map_err(io::Error::from)
+            .and_then(|json| write_config(QUEUE_FILE, json));
+        if let Err(e) = result {
+            warn!("Failed to save download queue: {e}");
+        }
+    }
+}
+
+/// 下载队列
+pub struct Queue {
+    runtime: Runtime,
+    client: ClientWithMiddleware,
+    settings: Arc<Mutex<Settings>>,
+    ui: Weak<MainWindow>,
+    inner: Mutex<Inner>,
+}
+
+impl Queue {
+    /// 创建队列, 恢复上次未完成的任务
+    pub fn new(ui: Weak<MainWindow>, settings: Arc<Mutex<Settings>>) -> Result<Arc<Self>> {
+        let mut tasks: Vec<Task> = match fs::read(config_dir().join(QUEUE_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!("Failed to parse download queue: {e}");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        for task in &mut tasks {
+            if task.status == Status::Running {
+                task.status = Status::Queued;
+            }
+        }
+        info!("Restored {} download tasks", tasks.len());
+
+        let queue = Arc::new(Self {
+            runtime: Runtime::new()?,
+            client: client(headers())?,
+            settings,
+            ui,
+            inner: Mutex::new(Inner {
+                tasks,
+                ..Default::default()
+            }),
+        });
+        queue.schedule(&mut queue.inner.lock().unwrap());
+        Ok(queue)
+    }
+
+    /// 添加任务, `inputs` 可以包含以空白或逗号分隔的多个视频标识
+    pub fn enqueue(self: &Arc<Self>, inputs: &str, root: &Path) {
+        let mut inner = self.inner.lock().unwrap();
+        let inputs = inputs
+            .split([',', '，', ' ', '\n'])
+            .map(str::trim)
+            .filter(|s| !s.is_empty());
+        for input in inputs {
+            inner.push(input, root, None);
+        }
+        self.schedule(&mut inner);
+    }
+
+    /// 重新下载已有目录 `dir` 中的视频
+    pub fn enqueue_dir(self: &Arc<Self>, input: &str, dir: &Path) {
+        let mut inner = self.inner.lock().unwrap();
+        let root = dir.parent().unwrap_or(dir);
+        inner.push(input, root, Some(dir.to_path_buf()));
+        self.schedule(&mut inner);
+    }
+
+    /// 暂停任务, 执行中的任务丢弃进行中的请求后停止
+    pub fn pause(self: &Arc<Self>, id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(token) = inner.tokens.remove(&id) {
+            token.cancel();
+        }
+        if let Some(task) = inner.task(id)
+            && matches!(task.status, Status::Queued | Status::Running)
+        {
+            task.status = Status::Paused;
+            task.message = "已暂停".into();
+        }
+        self.schedule(&mut inner);
+    }
+
+    /// 继续暂停的任务, 或重试失败的任务 (只重新下载缺失或损坏的节点)
+    pub fn resume(self: &Arc<Self>, id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(task) = inner.task(id)
+            && matches!(task.status, Status::Paused | Status::Failed | Status::Done)
+        {
+            task.status = Status::Queued;
+            task.message = "等待中".into();
+        }
+        self.schedule(&mut inner);
+    }
+
+    /// 取消并移除任务, 已下载的文件保留
+    pub fn remove(self: &Arc<Self>, id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(token) = inner.tokens.remove(&id) {
+            token.cancel();
+        }
+        inner.tasks.retain(|t| t.id != id);
+        if inner.selected == Some(id) {
+            inner.selected = None;
+        }
+        self.schedule(&mut inner);
+    }
+
+    /// 选择显示日志的任务
+    pub fn select(self: &Arc<Self>, id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.selected = Some(id);
+        self.refresh(&inner);
+    }
+
+    /// 启动等待中的任务, 保存队列并刷新界面
+    fn schedule(self: &Arc<Self>, inner: &mut Inner) {
+        let mut running = inner.tokens.len();
+        for task in &mut inner.tasks {
+            if running >= MAX_RUNNING {
+                break;
+            }
+            if task.status != Status::Queued {
+                continue;
+            }
+
+            info!("Starting task {}: `{}`", task.id, task.input);
+            task.status = Status::Running;
+            task.log.clear();
+            task.run += 1;
+            running += 1;
+
+            // 任务开始时的设置快照
+            let settings = self.settings.lock().unwrap().clone();
+            let (id, run) = (task.id, task.run);
+            let (input, root, dir) = (task.input.clone(), task.root.clone(), task.dir.clone());
+            let cancel = CancelToken::new();
+            let queue = self.clone();
+            inner.tokens.insert(id, cancel.clone());
+            let _ = self.runtime.spawn(async move {
+                let mut progress = |p| queue.update(id, run, p);
+                let observer = {
+                    let queue = queue.clone();
+                    move |event: &Event| queue.event(id, run, event)
+                };
+                let locate = |dir: &Path| queue.locate(id, run, dir.to_path_buf());
+                let result = observe(
+                    observer,
+                    execute(
+                        &queue.client,
+                        &input,
+                        &root,
+                        dir.as_deref(),
+                        &settings,
+                        &mut progress,
+                        locate,
+                        &cancel,
+                    ),
+                )
+                .await;
+                queue.finish(id, run, result);
+            });
+        }
+
+        inner.save();
+        self.refresh(inner);
+    }
+
+    /// 当前执行的任务
+    fn running(inner: &mut Inner, id: u64, run: u64) -> Option<&mut Task> {
+        inner
+            .task(id)
+            .filter(|t| t.run == run && t.status == Status::Running)
+    }
+
+    fn update(
+        &self,
+        id: u64,
+        run: u64,
+        Progress {
+            progress,
+            message,
+            transient,
+        }: Progress,
+    ) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(task) = Self::running(&mut inner, id, run) {
+            task.progress = progress;
+            if !transient {
+                task.log.push(message.clone());
+            }
+            task.message = message;
+            self.refresh_task(&inner, id, !transient);
+        }
+    }
+
+    /// 将需要用户关注的库事件写入任务日志
+    fn event(&self, id: u64, run: u64, event: &Event) {
+        let message = match event {
+            Event::Retry {
+                host,
+                attempt,
+                max,
+                backoff,
+            } => format!(
+                "`{host}` 风控, {} 秒后重试 ({attempt}/{max})",
+                backoff.as_secs()
+            ),
+            Event::Warning { message } => format!("警告: {message}"),
+            Event::NodeSkipped { id } => format!("跳过已下载的节点 {id}"),
+            _ => return,
+        };
+
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(task) = Self::running(&mut inner, id, run) {
+            task.log.push(message);
+            self.refresh_task(&inner, id, true);
+        }
+    }
+
+    /// 记录视频目录, 以便继续下载时跳过爬取或已下载的节点
+    fn locate(&self, id: u64, run: u64, dir: PathBuf) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(task) = Self::running(&mut inner, id, run) {
+            task.dir = Some(dir);
+            self.refresh_task(&inner, id, false);
+        }
+        inner.save();
+    }
+
+    fn finish(self: &Arc<Self>, id: u64, run: u64, result: Result<()>) {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(task) = Self::running(&mut inner, id, run) else {
+            return;
+        };
+        match result {
+            Ok(()) => {
+                info!("Task {id} done");
+                task.status = Status::Done;
+            }
+            Err(e) if is_cancelled(&e) => {
+                info!("Task {id} cancelled");
+                task.status = Status::Paused;
+                task.message = "已暂停".into();
+            }
+            Err(e) => {
+                warn!("Task {id} failed: {e}");
+                task.status = Status::Failed;
+                task.message = format!("失败: {e}");
+                task.log.push(task.message.clone());
+            }
+        }
+        inner.tokens.remove(&id);
+        self.schedule(&mut inner);
+    }
+
+    fn refresh(&self, inner: &Inner) {
+        let tasks: Vec<TaskItem> = inner.tasks.iter().map(Into::into).collect();
+        let log = inner
+            .selected
+            .and_then(|id| inner.tasks.iter().find(|t| t.id == id))
+            .map(|t| t.log.join("\n"))
+            .unwrap_or_default();
+
+        self.ui
+            .upgrade_in_event_loop(move |ui| {
+                let fetch = ui.global::<Fetch>();
+                fetch.set_tasks(ModelRc::new(VecModel::from(tasks)));
+                fetch.set_log(log.into());
+            })
+            .unwrap();
+    }
+
+    /// 只刷新一个任务所在的行, `logged` 时刷新其日志
+    ///
+    /// 传输进度更新频繁, 重建整个模型和日志会重置日志的滚动位置.
+    fn refresh_task(&self, inner: &Inner, id: u64, logged: bool) {
+        let Some(task) = inner.tasks.iter().find(|t| t.id == id) else {
+            return;
+        };
+        let item = TaskItem::from(task);
+        let log = (logged && inner.selected == Some(id)).then(|| task.log.join("\n"));
+
+        self.ui
+            .upgrade_in_event_loop(move |ui| {
+                let fetch = ui.global::<Fetch>();
+                let tasks = fetch.get_tasks();
+                let row = (0..tasks.row_count())
+                    .find(|&k| tasks.row_data(k).is_some_and(|t| t.id == item.id));
+                if let Some(row) = row {
+                    tasks.set_row_data(row, item);
+                }
+                if let Some(log) = log {
+                    fetch.set_log(log.into());
+                }
+            })
+            .unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+//! 设置
+//!
+//! 设置和下载队列位于平台配置目录 (如 `~/.config/bidown-ui/`),
+//! 日志默认位于平台数据目录 (如 `~/.local/share/bidown-ui/log/`).
+
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use bidown::{
+    model::Video,
+    subtitle,
+    video::{Danmaku, DownloadOptions, Extras, Overwrite, Quality},
+};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use slint::{ComponentHandle, Weak};
+
+use crate::{MainWindow, Options};
+
+//////// path ////////
+
+/// 应用目录名
+const APP_DIR: &str = "bidown-ui";
+/// 设置持久化文件
+const SETTINGS_FILE: &str = "settings.json";
+
+/// 配置目录, 无法确定平台目录时使用当前目录
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir().map_or_else(|| PathBuf::from("."), |d| d.join(APP_DIR))
+}
+
+/// 写入配置目录下的文件
+pub fn write_config(name: &str, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let dir = config_dir();
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(name), contents)
+}
+
+//////// settings ////////
+
+/// 下载模式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// 爬取剧情树并下载节点视频
+    #[default]
+    Download,
+    /// 只爬取剧情树
+    CrawlOnly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// 下载根目录
+    pub output: String,
+    pub mode: Mode,
+    /// 视频目录命名模板, 支持 `{bvid}`, `{title}`, `{author}`
+    pub naming: String,
+    pub download: DownloadOptions,
+    /// 日志目录, 为空时使用平台数据目录
+    pub log_dir: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            output: String::new(),
+            mode: Mode::default(),
+            naming: "{bvid}".into(),
+            download: DownloadOptions::default(),
+            log_dir: String::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// 读取设置, 不存在或无法解析时使用默认值
+    pub fn load() -> Self {
+        match fs::read(config_dir().join(SETTINGS_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!("Failed to parse settings: {e}");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let result = serde_json::to_string_pretty(self)
+            .map_err(io::Error::from)
+            .and_then(|json| write_config(SETTINGS_FILE, json));
+        if let Err(e) = result {
+            warn!("Failed to save settings: {e}");
+        }
+    }
+
+    pub fn log_dir(&self) -> PathBuf {
+        if !self.log_dir.is_empty() {
+            return PathBuf::from(&self.log_dir);
+        }
+        dirs::data_local_dir()
+            .map_or_else(|| PathBuf::from("./log"), |d| d.join(APP_DIR).join("log"))
+    }
+
+    /// 按命名模板生成视频目录名, 替换文件名中的非法字符
+    pub fn dir_name(&self, video: &Video) -> String {
+        let name = self
+            .naming
+            .replace("{bvid}", video.id.bvid())
+            .replace("{title}", &video.name)
+            .replace("{author}", &video.author);
+        let name: String = name
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect();
+
+        let name = name.trim().trim_end_matches('.');
+        if name.is_empty() {
+            video.id.bvid().to_string()
+        } else {
+            name.to_string()
+        }
+    }
+}
+
+//////// ui ////////
+
+const QUALITIES: [Quality; 4] = [
+    Quality::High,
+    Quality::Medium,
+    Quality::Low,
+    Quality::VeryLow,
+];
+
+impl Settings {
+    /// 写入界面
+    fn apply(&self, options: &Options) {
+        let DownloadOptions {
+            quality,
+            overwrite,
+            concurrency,
+            assets,
+            extras: Extras { subtitle, danmaku },
+        } = &self.download;
+
+        options.set_output(self.output.as_str().into());
+        options.set_mode(match self.mode {
+            Mode::Download => 0,
+            Mode::CrawlOnly => 1,
+        });
+        options.set_naming(self.naming.as_str().into());
+        options.set_quality(QUALITIES.iter().position(|q| q == quality).unwrap_or(0) as i32);
+        options.set_overwrite(match overwrite {
+            Overwrite::Resume => 0,
+            Overwrite::Replace => 1,
+        });
+        options.set_concurrency(*concurrency as i32);
+        options.set_assets(*assets);
+        options.set_subtitle(match subtitle {
+            None => 0,
+            Some(subtitle::Format::Srt) => 1,
+            Some(subtitle::Format::WebVtt) => 2,
+        });
+        options.set_danmaku(match danmaku {
+            None => 0,
+            Some(Danmaku::Xml) => 1,
+            Some(Danmaku::Protobuf) => 2,
+        });
+    }
+
+    /// 从界面读取, 界面中没有的项保持不变
+    fn read(&self, options: &Options) -> Self {
+        Self {
+            output: options.get_output().to_string(),
+            mode: match options.get_mode() {
+                1 => Mode::CrawlOnly,
+                _ => Mode::Download,
+            },
+            naming: options.get_naming().to_string(),
+            download: DownloadOptions {
+                quality: QUALITIES
+                    .get(options.get_quality() as usize)
+                    .copied()
+                    .unwrap_or_default(),
+                overwrite: match options.get_overwrite() {
+                    1 => Overwrite::Replace,
+                    _ => Overwrite::Resume,
+                },
+                concurrency: options.get_concurrency().max(1) as usize,
+                assets: options.get_assets(),
+                extras: Extras {
+                    subtitle: match options.get_subtitle() {
+                        1 => Some(subtitle::Format::Srt),
+                        2 => Some(subtitle::Format::WebVtt),
+                        _ => None,
+                    },
+                    danmaku: match options.get_danmaku() {
+                        1 => Some(Danmaku::Xml),
+                        2 => Some(Danmaku::Protobuf),
+                        _ => None,
+                    },
+                },
+            },
+            log_dir: self.log_dir.clone(),
+        }
+    }
+}
+
+//////// bind ////////
+
+pub fn bind_settings<'a>(
+    options: Options<'a>,
+    ui: Weak<MainWindow>,
+    settings: Arc<Mutex<Settings>>,
+) {
+    settings.lock().unwrap().apply(&options);
+
+    // 界面变化时保存
+    options.on_changed(move || {
+        let Some(ui) = ui.upgrade() else {
+            return;
+        };
+        let mut settings = settings.lock().unwrap();
+        *settings = settings.read(&ui.global::<Options>());
+        debug!("Settings changed: {settings:?}");
+        settings.save();
+    });
+}
@@ -0,0 +1,172 @@
+//! 求解页
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    rc::Rc,
+    thread,
+};
+
+use anyhow::{Context, Result};
+use bidown::{Progress, model::Video, solve::Step};
+use log::debug;
+use slint::{ComponentHandle, ModelRc, VecModel, Weak};
+
+use crate::{MainWindow, Solve, SolvedNode, utils::show_error};
+
+//////// solve ////////
+
+/// 解析以逗号 (或空白) 分隔的选项 id 列表
+fn parse_exclude(exclude: &str) -> Result<HashSet<usize>> {
+    exclude
+        .split([',', '，', ' ', '\n'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().with_context(|| format!("非法的选项 id `{s}`")))
+        .collect()
+}
+
+/// 路径描述, 如 `N1 →[C1] N2`
+fn describe(step: &Rc<Step<'_>>) -> String {
+    let steps: Vec<_> = step.iter().collect();
+    steps
+        .iter()
+        .rev()
+        .map(|s| match s.choice() {
+            Some(choice) => format!(" →[{}] {}", choice.name, s.node().name),
+            None => s.node().name.clone(),
+        })
+        .collect()
+}
+
+/// 求解结果 (可以跨线程传递)
+struct Solved {
+    id: usize,
+    name: String,
+    ending: bool,
+    path: String,
+}
+
+impl From<Solved> for SolvedNode {
+    fn from(value: Solved) -> Self {
+        let Solved {
+            id,
+            name,
+            ending,
+            path,
+        } = value;
+        Self {
+            id: id as i32,
+            name: name.into(),
+            ending,
+            path: path.into(),
+        }
+    }
+}
+
+fn solve_inner<P>(
+    path: &Path,
+    maxd: usize,
+    cutd: usize,
+    variable: bool,
+    exclude: &str,
+    mut log: impl FnMut(String),
+    progress: P,
+) -> Result<Vec<Solved>>
+where
+    P: FnMut(Progress),
+{
+    let exclude = parse_exclude(exclude)?;
+
+    log(format!("加载视频描述 `{}`...", path.to_string_lossy()));
+    let video = Video::from_file(path)?;
+
+    log(format!(
+        "开始求解 `{}`, 最大深度 {maxd}, 剪枝深度 {cutd}, 排除 {} 个选项",
+        video.name,
+        exclude.len()
+    ));
+    let solution =
+        video.solve_with_progress(maxd, cutd, |c| !exclude.contains(&c.id), variable, progress)?;
+
+    // 与求解示例一致, 结果写在描述文件旁边
+    let out = path.with_extension("sln.json");
+    File::create(&out)?.write_all(serde_json::to_string_pretty(&solution)?.as_bytes())?;
+
+    let total = video.graph.nodes.len();
+    let endings = solution.iter_leaf().count();
+    log(format!(
+        "求解完成! 共 {}/{total} 个节点, {endings} 个结局, 结果已写入 `{}`",
+        solution.len(),
+        out.to_string_lossy()
+    ));
+
+    Ok(solution
+        .iter()
+        .map(|step| Solved {
+            id: step.node().id,
+            name: step.node().name.clone(),
+            ending: step.node().is_leaf(),
+            path: describe(step),
+        })
+        .collect())
+}
+
+//////// bind ////////
+
+pub fn bind_solve<'a>(solve: Solve<'a>, ui: Weak<MainWindow>) {
+    solve.on_solve(move |path, maxd, cutd, variable, exclude| {
+        ui.upgrade_in_event_loop(|ui| {
+            let solve = ui.global::<Solve>();
+            solve.set_is_solving(true);
+            solve.set_progress(0.);
+            solve.set_log("".into());
+            solve.set_nodes(ModelRc::default());
+        })
+        .unwrap();
+
+        let path = PathBuf::from(path.as_str());
+        let exclude = exclude.to_string();
+        let (maxd, cutd) = (maxd.max(0) as usize, cutd.max(0) as usize);
+
+        let log = {
+            let ui = ui.clone();
+            let mut messages = Vec::new();
+            move |message: String| {
+                messages.push(message);
+                let text = messages.join("\n");
+                ui.upgrade_in_event_loop(move |ui| ui.global::<Solve>().set_log(text.into()))
+                    .unwrap();
+            }
+        };
+
+        let progress = {
+            let ui = ui.clone();
+            move |progress: Progress| {
+                debug!("Updating solving progress...");
+                let progress = progress.fraction().unwrap_or_default() as f32;
+                ui.upgrade_in_event_loop(move |ui| ui.global::<Solve>().set_progress(progress))
+                    .unwrap();
+            }
+        };
+
+        let _ = thread::spawn({
+            let ui = ui.clone();
+            move || {
+                debug!("Solving thread spawned");
+                let result = solve_inner(&path, maxd, cutd, variable, &exclude, log, progress);
+
+                let nodes = result.inspect_err(show_error).unwrap_or_default();
+                ui.upgrade_in_event_loop(move |ui| {
+                    let solve = ui.global::<Solve>();
+                    let nodes: Vec<SolvedNode> = nodes.into_iter().map(Into::into).collect();
+                    solve.set_nodes(ModelRc::new(VecModel::from(nodes)));
+                    solve.set_is_solving(false);
+                })
+                .unwrap();
+            }
+        });
+    });
+}
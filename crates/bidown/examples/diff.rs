@@ -0,0 +1,34 @@
+//! 互动视频描述比较示例
+//!
+//! 此示例将比较 `./demo-{VIDEO}.json` 与 `./demo-{VIDEO}.new.json`,
+//! 输出可读报告, 并将 JSON 报告保存到 `./demo-{VIDEO}.diff.json`
+
+use std::{env, error::Error, fs::File, io::Write};
+
+use bidown::model::Video;
+use env_logger::Env;
+use log::{debug, info};
+
+const VIDEO: &str = "BV1vSNbzgEQF";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // 1. 启动日志
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let root = env::current_dir()?;
+
+    // 2. 解析互动视频描述
+    let old = Video::from_file(&root.join(format!("demo-{VIDEO}.json")))?;
+    let new = Video::from_file(&root.join(format!("demo-{VIDEO}.new.json")))?;
+
+    // 3. 比较
+    let diff = old.diff(&new);
+    info!("Report:\n{diff}");
+
+    // 4. 写入本地文件
+    let path = root.join(format!("demo-{VIDEO}.diff.json"));
+    debug!("Writing to {}", path.to_string_lossy());
+    File::create(&path)?.write_all(serde_json::to_string_pretty(&diff)?.as_bytes())?;
+
+    info!("Done! see at `{}`", path.to_string_lossy());
+    Ok(())
+}
@@ -2,9 +2,9 @@
 //!
 //! 此示例将获取互动视频相关数据并保存到 `./demo-{VIDEO}.json`
 
-use std::{env, error::Error, fs::File, io::Write, time::Duration};
+use std::{env, error::Error, time::Duration};
 
-use bidown::model::Video;
+use bidown::{cache::Cache, id::VideoId, limit::RateLimit, model::Video};
 use env_logger::Env;
 use log::{debug, info};
 use reqwest::{
@@ -15,6 +15,7 @@ use reqwest_middleware::ClientBuilder;
 use reqwest_retry::{RetryTransientMiddleware, policies::ExponentialBackoff};
 
 const VIDEO: &str = "BV1vSNbzgEQF";
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
 
 //////// utility ////////
 
@@ -54,19 +55,22 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .retry_bounds(Duration::from_secs(4), Duration::from_secs(16))
         .build_with_max_retries(3);
 
-    // 4. 构建客户端中间件
+    // 4. 构建客户端中间件 (响应缓存在 `./cache`)
+    let cache = Cache::new(env::current_dir()?.join("cache")).with_ttl(CACHE_TTL);
     let client = ClientBuilder::new(client)
+        .with(cache)
         .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .with(RateLimit::default())
         .build();
 
     // 5. 执行互动视频爬取
-    let video = Video::fetch(&client, VIDEO, |_| ()).await?;
-    let video = serde_json::to_string_pretty(&video)?;
+    let bvid = VideoId::resolve(&client, VIDEO).await?;
+    let video = Video::fetch(&client, &bvid, |_| ()).await?;
 
     // 6. 写入本地文件
     let path = env::current_dir()?.join(format!("demo-{VIDEO}.json"));
     debug!("Writing to {}", path.to_string_lossy());
-    File::create(&path)?.write_all(video.as_bytes())?;
+    video.to_file(&path)?;
 
     info!("Done! see at `{}`", path.to_string_lossy());
     Ok(())
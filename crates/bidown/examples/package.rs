@@ -0,0 +1,33 @@
+//! 互动视频打包示例
+//!
+//! 此示例将 bidown-ui 的下载目录 `./{VIDEO}` 打包为 `./{VIDEO}.zip`, 并校验包内容
+
+use std::{env, error::Error};
+
+use bidown::package::Package;
+use env_logger::Env;
+use log::{info, warn};
+
+const VIDEO: &str = "BV1vSNbzgEQF";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // 1. 启动日志
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let root = env::current_dir()?;
+
+    // 2. 打包
+    let path = root.join(format!("{VIDEO}.zip"));
+    let manifest = Package::create(&root.join(VIDEO), &path)?;
+    for entry in &manifest.entries {
+        info!("Packed `{}`, {} bytes", entry.path, entry.size);
+    }
+
+    // 3. 校验
+    let report = Package::open(&path)?.verify()?;
+    if !report.is_ok() {
+        warn!("Package is broken: {report:?}");
+    }
+
+    info!("Done! see at `{}`", path.to_string_lossy());
+    Ok(())
+}
@@ -0,0 +1,26 @@
+//! 离线播放器导出示例
+//!
+//! 此示例将在 bidown-ui 的下载目录 `./{VIDEO}` 中生成 `index.html`
+
+use std::{env, error::Error};
+
+use bidown::{model::Video, package::DATA_FILE};
+use env_logger::Env;
+use log::info;
+
+const VIDEO: &str = "BV1vSNbzgEQF";
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // 1. 启动日志
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let root = env::current_dir()?.join(VIDEO);
+
+    // 2. 解析互动视频描述
+    let video = Video::from_file(&root.join(DATA_FILE))?;
+
+    // 3. 导出播放器
+    let path = video.export_player(&root)?;
+
+    info!("Done! open `{}` in browser", path.to_string_lossy());
+    Ok(())
+}
@@ -0,0 +1,24 @@
+//! 描述文件 JSON Schema 生成示例
+//!
+//! 此示例将生成 data.json 的 JSON Schema 并保存到 `schema/data.schema.json`
+
+use std::{error::Error, fs::File, io::Write, path::Path};
+
+use bidown::document::Document;
+use env_logger::Env;
+use log::info;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // 1. 启动日志
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+
+    // 2. 生成 schema
+    let schema = serde_json::to_string_pretty(&Document::json_schema())?;
+
+    // 3. 写入本地文件
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema/data.schema.json");
+    File::create(&path)?.write_all(format!("{schema}\n").as_bytes())?;
+
+    info!("Done! see at `{}`", path.to_string_lossy());
+    Ok(())
+}
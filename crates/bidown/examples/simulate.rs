@@ -0,0 +1,103 @@
+//! 互动视频游玩模拟示例
+//!
+//! 此示例将在终端中游玩 `./demo-{VIDEO}.json` 对应的视频描述
+//!
+//! 输入选项序号进行选择, 空行完成自动跳转, `u` 撤销, `r` 重新开始, `s` 存档, `l` 读档, `q` 退出
+
+use std::{
+    env,
+    error::Error,
+    io::{self, BufRead, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bidown::{
+    model::{NodeConfig, VariableConfig, Video},
+    simulate::{Save, Simulator},
+};
+use env_logger::Env;
+use log::debug;
+
+const VIDEO: &str = "BV1vSNbzgEQF";
+
+fn show(simulator: &Simulator) -> Result<(), Box<dyn Error>> {
+    let node = simulator.node();
+    println!("\n== [{}] {} ==", node.id, node.name);
+
+    for (v, value) in simulator.variables() {
+        if let VariableConfig::Normal { show: true, .. } = v.config {
+            println!("  {}: {value}", v.name);
+        }
+    }
+
+    if simulator.is_end() {
+        println!("  (结束)");
+    }
+    if let NodeConfig::Jump { .. } = node.config {
+        println!("  (自动跳转)");
+    }
+    for (k, branch) in simulator.branches()?.iter().enumerate() {
+        let mark = if branch.available() { ' ' } else { 'x' };
+        println!("  {mark} {}. {}", k + 1, branch.choice.name);
+        for (condition, ok) in &branch.conditions {
+            println!("        {condition} => {ok}");
+        }
+    }
+
+    print!("> ");
+    io::stdout().flush()?;
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // 1. 启动日志
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let root = env::current_dir()?;
+
+    // 2. 解析互动视频描述
+    let path = root.join(format!("demo-{VIDEO}.json"));
+    debug!("Loading video graph at `{}`", path.to_string_lossy());
+    let video = Video::from_file(&path)?;
+
+    // 3. 以当前时间为种子开始游玩
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut simulator = Simulator::new(&video, seed)?;
+    let save_path = root.join(format!("demo-{VIDEO}.save.json"));
+
+    // 4. 交互
+    show(&simulator)?;
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        match line.trim() {
+            "q" => break,
+            "" if !simulator.advance()? => println!("当前节点不会自动跳转"),
+            "" => {}
+            "u" if !simulator.undo() => println!("没有可撤销的选择"),
+            "u" => {}
+            "r" => simulator.restart(),
+            "s" => {
+                simulator.save().to_file(&save_path)?;
+                println!("已存档至 `{}`", save_path.to_string_lossy());
+            }
+            "l" => simulator = Simulator::load(&video, Save::from_file(&save_path)?)?,
+            input => {
+                let branches = simulator.branches()?;
+                let Some(branch) = input
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|k| branches.get(k.wrapping_sub(1)))
+                else {
+                    println!("未知输入 `{input}`");
+                    continue;
+                };
+                if let Err(e) = simulator.choose(branch.choice.id) {
+                    println!("{e}");
+                    continue;
+                }
+            }
+        }
+        show(&simulator)?;
+    }
+
+    Ok(())
+}
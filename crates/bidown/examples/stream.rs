@@ -0,0 +1,87 @@
+//! 互动视频流式爬取示例
+//!
+//! 此示例将逐个获取剧情树节点并即时输出, 最后保存到 `./demo-{VIDEO}.json`
+
+use std::{env, error::Error, time::Duration};
+
+use bidown::{id::VideoId, limit::RateLimit, model::Video};
+use env_logger::Env;
+use futures::TryStreamExt;
+use log::{debug, info};
+use reqwest::{
+    Client,
+    header::{ACCEPT, ACCEPT_ENCODING, ACCEPT_LANGUAGE, HeaderMap, HeaderValue, USER_AGENT},
+};
+use reqwest_middleware::ClientBuilder;
+use reqwest_retry::{RetryTransientMiddleware, policies::ExponentialBackoff};
+
+const VIDEO: &str = "BV1vSNbzgEQF";
+
+//////// utility ////////
+
+/// 配置请求头
+fn headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        USER_AGENT,
+        HeaderValue::from_static(
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/145.0.0.0 Safari/537.36 Edg/145.0.0.0",
+        ),
+    );
+    headers.insert(
+        ACCEPT,
+        HeaderValue::from_static("application/json, text/plain, */*"),
+    );
+    headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("br, zstd"));
+    headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("zh-CN,zh;q=0.9"));
+
+    headers
+}
+
+//////// main ////////
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    // 1. 启动日志
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+
+    // 2. 配置客户端
+    debug!("Building client");
+    let client = Client::builder().default_headers(headers()).build()?;
+
+    // 3. 配置请求策略
+    let retry_policy = ExponentialBackoff::builder()
+        .retry_bounds(Duration::from_secs(4), Duration::from_secs(16))
+        .build_with_max_retries(3);
+
+    // 4. 构建客户端中间件
+    let client = ClientBuilder::new(client)
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .with(RateLimit::default())
+        .build();
+
+    // 5. 获取元数据, 得到节点流
+    let bvid = VideoId::resolve(&client, VIDEO).await?;
+    let (mut video, nodes) = Video::fetch_stream(&client, &bvid).await?;
+    let mut nodes = Box::pin(nodes);
+
+    // 6. 逐个处理节点, 处理期间不会请求下一个节点
+    while let Some(node) = nodes.try_next().await? {
+        info!(
+            "Received node {} `{}` with {} choices",
+            node.id,
+            node.name,
+            node.config.choices().len()
+        );
+        video.graph.nodes.push(node);
+    }
+
+    // 7. 写入本地文件
+    let path = env::current_dir()?.join(format!("demo-{VIDEO}.json"));
+    debug!("Writing to {}", path.to_string_lossy());
+    video.to_file(&path)?;
+
+    info!("Done! see at `{}`", path.to_string_lossy());
+    Ok(())
+}
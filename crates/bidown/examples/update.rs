@@ -0,0 +1,82 @@
+//! 互动视频描述更新示例
+//!
+//! 此示例将检查 `./demo-{VIDEO}.json` 的剧情图版本, 若已更新则重新爬取,
+//! 覆盖原文件, 并将差异保存到 `./demo-{VIDEO}.diff.json`
+
+use std::{env, error::Error, fs::File, io::Write, time::Duration};
+
+use bidown::{limit::RateLimit, model::Video};
+use env_logger::Env;
+use log::{debug, info};
+use reqwest::{
+    Client,
+    header::{ACCEPT, ACCEPT_ENCODING, ACCEPT_LANGUAGE, HeaderMap, HeaderValue, USER_AGENT},
+};
+use reqwest_middleware::ClientBuilder;
+use reqwest_retry::{RetryTransientMiddleware, policies::ExponentialBackoff};
+
+const VIDEO: &str = "BV1vSNbzgEQF";
+
+//////// utility ////////
+
+/// 配置请求头
+fn headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        USER_AGENT,
+        HeaderValue::from_static(
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/145.0.0.0 Safari/537.36 Edg/145.0.0.0",
+        ),
+    );
+    headers.insert(
+        ACCEPT,
+        HeaderValue::from_static("application/json, text/plain, */*"),
+    );
+    headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("br, zstd"));
+    headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("zh-CN,zh;q=0.9"));
+
+    headers
+}
+
+//////// main ////////
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    // 1. 启动日志
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let root = env::current_dir()?;
+
+    // 2. 解析互动视频描述
+    let path = root.join(format!("demo-{VIDEO}.json"));
+    debug!("Loading video graph at `{}`", path.to_string_lossy());
+    let video = Video::from_file(&path)?;
+
+    // 3. 配置客户端
+    debug!("Building client");
+    let client = Client::builder().default_headers(headers()).build()?;
+    let retry_policy = ExponentialBackoff::builder()
+        .retry_bounds(Duration::from_secs(4), Duration::from_secs(16))
+        .build_with_max_retries(3);
+    let client = ClientBuilder::new(client)
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .with(RateLimit::default())
+        .build();
+
+    // 4. 检查更新
+    let Some((video, diff)) = video.update(&client, |_| ()).await? else {
+        info!("Already up to date!");
+        return Ok(());
+    };
+
+    info!("Video updated:\n{diff}");
+
+    // 5. 写入本地文件
+    video.to_file(&path)?;
+    let diff_path = root.join(format!("demo-{VIDEO}.diff.json"));
+    debug!("Writing to {}", diff_path.to_string_lossy());
+    File::create(&diff_path)?.write_all(serde_json::to_string_pretty(&diff)?.as_bytes())?;
+
+    info!("Done! see at `{}`", diff_path.to_string_lossy());
+    Ok(())
+}
@@ -4,7 +4,12 @@
 
 use std::{env, error::Error, time::Duration};
 
-use bidown::{model::Video, video::Quality};
+use bidown::{
+    limit::RateLimit,
+    model::Video,
+    subtitle,
+    video::{Danmaku, Extras, Quality},
+};
 use env_logger::Env;
 use log::{debug, info};
 use reqwest::{
@@ -72,12 +77,25 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // 5. 构建客户端中间件
     let client = ClientBuilder::new(client)
         .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .with(RateLimit::default())
         .build();
 
     // 6. 下载相关视频并写入本地文件
     let path = env::current_dir()?.join(format!("demo-{VIDEO}.video"));
     video.download(&client, &path, QUALITY, |_| ()).await?;
 
+    // 7. 下载封面和缩略图
+    video.download_assets(&client, &path).await?;
+
+    // 8. 下载字幕和弹幕
+    let extras = Extras {
+        subtitle: Some(subtitle::Format::Srt),
+        danmaku: Some(Danmaku::Xml),
+    };
+    video
+        .download_extras(&client, &path, extras, |_| ())
+        .await?;
+
     info!("Done! see at `{}`", path.to_string_lossy());
     Ok(())
 }
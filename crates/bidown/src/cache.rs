@@ -0,0 +1,402 @@
+//! 响应缓存中间件
+//!
+//! 以请求 URL (接口 + 参数) 为键, 将成功的 JSON 响应保存在缓存目录下,
+//! 以便重放剧情图爬取, 或在离线状态下复现爬取问题.
+//!
+//! # Notes
+//!
+//! - 只缓存 GET 请求, 且业务码为 0 的 JSON 响应 (视频流不会被缓存)
+//!
+//! - 只缓存 [`Cache::with_endpoints`] 指定的接口, 默认为 [`DEFAULT_ENDPOINTS`]
+//!
+//! - 请将此中间件放在重试和限流中间件之前, 命中缓存时不消耗令牌
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::Extensions;
+use log::{debug, warn};
+use reqwest::{
+    Method, Request, Response, StatusCode, Url, Version,
+    header::{CONTENT_TYPE, HeaderMap, HeaderValue},
+};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::fs;
+
+use crate::{
+    event::warning,
+    utils::{rebuild_response, response_code},
+};
+
+//////// entry ////////
+
+/// 缓存条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    url: String,
+    time: u64,
+    content_type: String,
+    body: String,
+}
+
+impl Entry {
+    fn is_fresh(&self, ttl: Option<Duration>, now: u64) -> bool {
+        ttl.is_none_or(|ttl| now.saturating_sub(self.time) <= ttl.as_secs())
+    }
+
+    fn into_response(self, url: Url) -> Response {
+        let mut headers = HeaderMap::new();
+        if let Ok(v) = HeaderValue::from_str(&self.content_type) {
+            headers.insert(CONTENT_TYPE, v);
+        }
+        rebuild_response(
+            url,
+            StatusCode::OK,
+            Version::HTTP_11,
+            headers,
+            Bytes::from(self.body),
+        )
+    }
+}
+
+/// 当前 UNIX 时间 (秒)
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// 稳定的 64 位 FNV-1a 哈希, 用于生成缓存文件名
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+//////// cache ////////
+
+/// 缓存模式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// 优先读取未过期的缓存, 未命中时请求网络并写入缓存
+    #[default]
+    Normal,
+    /// 只读取缓存 (忽略过期时间), 未命中时报错, 不访问网络
+    Offline,
+    /// 总是请求网络并刷新缓存
+    Refresh,
+}
+
+/// 默认缓存的接口路径
+///
+/// | 接口 | 说明 |
+/// | --- | --- |
+/// | `/x/stein/edgeinfo_v2` | 剧情图节点, 按剧情图版本请求 |
+/// | `/x/web-interface/view` | 视频元数据 |
+///
+/// `/x/player/v2` 含当前剧情图版本 (更新检查依赖它), `/x/player/playurl` 含会过期的签名地址,
+/// 均不缓存.
+pub const DEFAULT_ENDPOINTS: [&str; 2] = ["/x/stein/edgeinfo_v2", "/x/web-interface/view"];
+
+/// 默认过期时间
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// 响应缓存中间件
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+    mode: Mode,
+    endpoints: Vec<String>,
+}
+
+impl Cache {
+    /// 在 `dir` 下建立缓存, 默认缓存 [`DEFAULT_ENDPOINTS`], [`DEFAULT_TTL`] 后过期
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: Some(DEFAULT_TTL),
+            mode: Mode::default(),
+            endpoints: DEFAULT_ENDPOINTS.map(str::to_string).to_vec(),
+        }
+    }
+
+    /// 过期时间, `None` 时永不过期
+    pub fn with_ttl(mut self, ttl: impl Into<Option<Duration>>) -> Self {
+        self.ttl = ttl.into();
+        self
+    }
+
+    /// 缓存的接口路径, 替换默认值
+    pub fn with_endpoints<I, S>(mut self, endpoints: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.endpoints = endpoints.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", fnv1a(url)))
+    }
+
+    /// 请求是否可以缓存
+    fn is_cacheable(&self, req: &Request) -> bool {
+        req.method() == Method::GET && self.endpoints.iter().any(|e| req.url().path() == e)
+    }
+
+    /// 读取缓存, 不存在或已过期时返回 `None`
+    async fn load(&self, url: &str) -> Result<Option<Entry>> {
+        let bytes = match fs::read(self.path(url)).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let entry: Entry = serde_json::from_slice(&bytes)?;
+        let ttl = match self.mode {
+            Mode::Offline => None,
+            _ => self.ttl,
+        };
+        Ok((entry.url == url && entry.is_fresh(ttl, now())).then_some(entry))
+    }
+
+    async fn store(&self, entry: &Entry) -> Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        fs::write(self.path(&entry.url), serde_json::to_vec(entry)?).await?;
+        Ok(())
+    }
+
+    /// 请求网络, 并在响应可缓存时写入缓存
+    async fn fetch(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let response = next.run(req, extensions).await?;
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        if !response.status().is_success() || !content_type.contains("json") {
+            return Ok(response);
+        }
+
+        let url = response.url().clone();
+        let status = response.status();
+        let version = response.version();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+
+        if response_code(&body) == Some(0)
+            && let Ok(text) = str::from_utf8(&body)
+        {
+            let entry = Entry {
+                url: url.to_string(),
+                time: now(),
+                content_type,
+                body: text.to_string(),
+            };
+            debug!("Caching response of `{url}`");
+            if let Err(e) = self.store(&entry).await {
+                warn!("Failed to cache response of `{url}`: {e}");
+                warning(format!("缓存 `{url}` 的响应失败: {e}"));
+            }
+        }
+
+        Ok(rebuild_response(url, status, version, headers, body))
+    }
+}
+
+#[async_trait]
+impl Middleware for Cache {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let url = req.url().clone();
+        let key = url.to_string();
+
+        if !self.is_cacheable(&req) {
+            return match self.mode {
+                Mode::Offline => Err(reqwest_middleware::Error::middleware(Error::Miss(key))),
+                _ => next.run(req, extensions).await,
+            };
+        }
+
+        if self.mode != Mode::Refresh {
+            match self.load(&key).await {
+                Ok(Some(entry)) => {
+                    debug!("Cache hit for `{key}`");
+                    return Ok(entry.into_response(url));
+                }
+                Ok(None) => (),
+                Err(e) => {
+                    warn!("Failed to read cache of `{key}`: {e}");
+                    warning(format!("读取 `{key}` 的缓存失败: {e}"));
+                }
+            }
+
+            if self.mode == Mode::Offline {
+                return Err(reqwest_middleware::Error::middleware(Error::Miss(key)));
+            }
+        }
+
+        self.fetch(req, extensions, next).await
+    }
+}
+
+//////// service ////////
+
+/// 缓存过程的返回类型
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// 缓存过程的错误类型
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("离线模式下缓存未命中: `{0}`")]
+    Miss(String),
+
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::Duration,
+    };
+
+    use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+    use tempfile::tempdir;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::{Cache, Entry, Error, Mode, fnv1a};
+
+    const BODY: &str = r#"{"code":0,"data":{}}"#;
+
+    /// 本地 HTTP 服务, 返回地址和收到的请求数
+    async fn serve() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+
+        let counter = count.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let Ok(n @ 1..) = stream.read(&mut buffer).await else {
+                        break;
+                    };
+                    request.extend_from_slice(&buffer[..n]);
+                }
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{BODY}",
+                    BODY.len()
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (base, count)
+    }
+
+    fn client(cache: Cache) -> ClientWithMiddleware {
+        ClientBuilder::new(reqwest::Client::new())
+            .with(cache)
+            .build()
+    }
+
+    async fn get(client: &ClientWithMiddleware, url: &str) -> reqwest_middleware::Result<String> {
+        Ok(client.get(url).send().await?.text().await?)
+    }
+
+    #[tokio::test]
+    async fn test_middleware() {
+        let (base, count) = serve().await;
+        let dir = tempdir().unwrap();
+        let edge = format!("{base}/x/stein/edgeinfo_v2?bvid=BV17x411w7KC&graph_version=1");
+        let playurl = format!("{base}/x/player/playurl?bvid=BV17x411w7KC&cid=1");
+
+        // 未命中时请求网络并写入缓存, 之后命中
+        let normal = client(Cache::new(dir.path()));
+        assert_eq!(get(&normal, &edge).await.unwrap(), BODY);
+        assert_eq!(get(&normal, &edge).await.unwrap(), BODY);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // 不在缓存列表中的接口总是请求网络
+        assert_eq!(get(&normal, &playurl).await.unwrap(), BODY);
+        assert_eq!(get(&normal, &playurl).await.unwrap(), BODY);
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        // 离线模式只读取缓存
+        let offline = client(Cache::new(dir.path()).with_mode(Mode::Offline));
+        assert_eq!(get(&offline, &edge).await.unwrap(), BODY);
+        for url in [&playurl, &format!("{edge}&edge_id=2")] {
+            let error = get(&offline, url).await.unwrap_err();
+            let reqwest_middleware::Error::Middleware(error) = error else {
+                panic!("expected middleware error, got {error}");
+            };
+            assert!(matches!(error.downcast_ref(), Some(Error::Miss(_))));
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_entry_fresh() {
+        let entry = Entry {
+            url: "https://api.bilibili.com/".to_string(),
+            time: 100,
+            content_type: "application/json".to_string(),
+            body: "{}".to_string(),
+        };
+
+        assert!(entry.is_fresh(None, u64::MAX));
+        assert!(entry.is_fresh(Some(Duration::from_secs(10)), 110));
+        assert!(!entry.is_fresh(Some(Duration::from_secs(10)), 111));
+    }
+
+    #[test]
+    fn test_fnv1a_stable() {
+        assert_eq!(fnv1a(""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a("a"), 0xaf63dc4c8601ec8c);
+    }
+}
@@ -0,0 +1,95 @@
+//! 取消令牌
+//!
+//! 由调用方持有并在任意线程调用 [`CancelToken::cancel`], 爬取和下载在请求之间检查令牌,
+//! 进行中的请求被丢弃, 不写入任何文件, 随后返回各模块的 `Cancelled` 错误.
+
+use std::{
+    future::Future,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use tokio::sync::Notify;
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// 取消令牌, 克隆的令牌共享状态
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<Inner>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 请求取消, 唤醒所有等待中的操作
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// 等待直到被取消
+    pub async fn cancelled(&self) {
+        loop {
+            // 先注册再检查, 避免错过唤醒
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// 执行 `future`, 已取消或执行中被取消时返回 `None`
+    pub async fn run<F: Future>(&self, future: F) -> Option<F::Output> {
+        if self.is_cancelled() {
+            return None;
+        }
+        tokio::select! {
+            biased;
+            _ = self.cancelled() => None,
+            output = future => Some(output),
+        }
+    }
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::CancelToken;
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let token = CancelToken::new();
+        assert_eq!(token.run(async { 1 }).await, Some(1));
+
+        // 执行中取消
+        let waiting = token.run(tokio::time::sleep(Duration::from_secs(60)));
+        let canceller = {
+            let token = token.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                token.cancel();
+            }
+        };
+        let (output, ()) = tokio::join!(waiting, canceller);
+        assert_eq!(output, None);
+
+        // 已取消时不再执行
+        assert!(token.is_cancelled());
+        assert_eq!(token.run(async { 1 }).await, None);
+    }
+}
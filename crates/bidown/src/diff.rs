@@ -0,0 +1,388 @@
+//! 互动视频描述差异
+//!
+//! 模型的 `PartialEq` 只比较 id, 此模块逐字段比较两份描述,
+//! 结果可以序列化为 JSON 报告, 也可以通过 `Display` 输出可读报告.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Display, Formatter},
+};
+
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+
+use crate::model::{Choice, Node, Variable, Video};
+
+//////// model ////////
+
+/// 字段变化, 缺失的字段记为 `null`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Field {
+    pub field: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+/// 同 id 项的字段变化
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Changed<K> {
+    pub id: K,
+    pub fields: Vec<Field>,
+}
+
+/// 按 id 比较的集合差异
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Changes<K> {
+    pub added: Vec<K>,
+    pub removed: Vec<K>,
+    pub changed: Vec<Changed<K>>,
+}
+
+impl<K> Default for Changes<K> {
+    fn default() -> Self {
+        Self {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        }
+    }
+}
+
+impl<K> Changes<K> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// 互动视频描述差异
+///
+/// # Notes
+///
+/// - 节点的 `choices` 字段只比较选项 id 列表, 选项内容的变化记在 `choices` 中
+///
+/// - 条件和修改以表达式字符串比较, 如 `$v1 <= 1`, `$v2 = $v2 + 0.5`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Diff {
+    pub metadata: Vec<Field>,
+    pub variables: Changes<String>,
+    pub nodes: Changes<usize>,
+    pub choices: Changes<usize>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.metadata.is_empty()
+            && self.variables.is_empty()
+            && self.nodes.is_empty()
+            && self.choices.is_empty()
+    }
+}
+
+//////// compare ////////
+
+/// 可逐字段比较的项
+trait Compare {
+    type Key: Ord + Clone;
+
+    fn key(&self) -> Self::Key;
+
+    /// 投影为用于比较的 JSON 对象
+    fn project(&self) -> Value;
+}
+
+impl Compare for Variable {
+    type Key = String;
+
+    fn key(&self) -> String {
+        self.id.clone()
+    }
+
+    fn project(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+impl Compare for Node {
+    type Key = usize;
+
+    fn key(&self) -> usize {
+        self.id
+    }
+
+    fn project(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(choices) = value.get_mut("choices") {
+            *choices = self.config.choices().iter().map(|c| c.id).collect();
+        }
+        value
+    }
+}
+
+impl Compare for Choice {
+    type Key = usize;
+
+    fn key(&self) -> usize {
+        self.id
+    }
+
+    fn project(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        let join = |exprs: Vec<String>, sep| Value::String(exprs.join(sep));
+        value["conditions"] = join(
+            self.conditions.iter().map(|c| c.to_string()).collect(),
+            " && ",
+        );
+        value["changes"] = join(self.changes.iter().map(|c| c.to_string()).collect(), "; ");
+        value
+    }
+}
+
+/// 逐字段比较两个 JSON 对象
+fn compare_fields(old: &Value, new: &Value) -> Vec<Field> {
+    let empty = Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter_map(|k| {
+            let o = old.get(k).unwrap_or(&Value::Null);
+            let n = new.get(k).unwrap_or(&Value::Null);
+            (o != n).then(|| Field {
+                field: k.clone(),
+                old: o.clone(),
+                new: n.clone(),
+            })
+        })
+        .collect()
+}
+
+impl<K: Ord + Clone> Changes<K> {
+    fn compare<'a, T, I>(old: I, new: I) -> Self
+    where
+        T: Compare<Key = K> + 'a,
+        I: Iterator<Item = &'a T>,
+    {
+        let old: BTreeMap<K, &T> = old.map(|t| (t.key(), t)).collect();
+        let new: BTreeMap<K, &T> = new.map(|t| (t.key(), t)).collect();
+        let mut changes = Self::default();
+
+        for (k, o) in &old {
+            let Some(n) = new.get(k) else {
+                changes.removed.push(k.clone());
+                continue;
+            };
+            let fields = compare_fields(&o.project(), &n.project());
+            if !fields.is_empty() {
+                changes.changed.push(Changed {
+                    id: k.clone(),
+                    fields,
+                });
+            }
+        }
+        changes.added = new
+            .keys()
+            .filter(|k| !old.contains_key(k))
+            .cloned()
+            .collect();
+
+        changes
+    }
+}
+
+/// 元数据投影
+fn metadata(video: &Video) -> Value {
+    json!({
+        "id": video.id,
+        "name": video.name,
+        "cover": video.cover,
+        "description": video.description,
+        "author": video.author,
+        "avatar": video.avatar,
+        "version": video.version,
+        "root": video.graph.root,
+    })
+}
+
+fn choices(video: &Video) -> impl Iterator<Item = &Choice> {
+    video.graph.nodes.iter().flat_map(|n| n.config.choices())
+}
+
+impl Video {
+    /// 逐字段比较两份描述
+    pub fn diff(&self, other: &Video) -> Diff {
+        Diff {
+            metadata: compare_fields(&metadata(self), &metadata(other)),
+            variables: Changes::compare(self.variables.iter(), other.variables.iter()),
+            nodes: Changes::compare(self.graph.nodes.iter(), other.graph.nodes.iter()),
+            choices: Changes::compare(choices(self), choices(other)),
+        }
+    }
+}
+
+//////// report ////////
+
+impl Display for Field {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.old, self.new)
+    }
+}
+
+impl<K: Display> Display for Changes<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for k in &self.added {
+            writeln!(f, "  + {k}")?;
+        }
+        for k in &self.removed {
+            writeln!(f, "  - {k}")?;
+        }
+        for Changed { id, fields } in &self.changed {
+            writeln!(f, "  ~ {id}")?;
+            for field in fields {
+                writeln!(f, "      {field}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Diff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "无差异");
+        }
+
+        if !self.metadata.is_empty() {
+            writeln!(f, "元数据:")?;
+            for field in &self.metadata {
+                writeln!(f, "  ~ {field}")?;
+            }
+        }
+
+        let sections: [(&str, &dyn Display, bool); 3] = [
+            ("变量", &self.variables, self.variables.is_empty()),
+            ("节点", &self.nodes, self.nodes.is_empty()),
+            ("选项", &self.choices, self.choices.is_empty()),
+        ];
+        for (title, changes, empty) in sections {
+            if !empty {
+                write!(f, "{title}:\n{changes}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{Changed, Field};
+
+    use crate::model::Video;
+
+    const OLD: &str = r#"{
+        "id": "BV17x411w7KC", "name": "OLD", "cover": "", "description": "", "author": "",
+        "variables": [
+            {"id": "$v1", "name": "V1", "type": "normal", "default": 0, "show": true},
+            {"id": "$v2", "name": "V2", "type": "random"}
+        ],
+        "graph": {"root": 1, "nodes": [
+            {"id": 1, "name": "N1", "type": "choice", "duration": 5000, "default": null, "choices": [
+                {"id": 11, "name": "C1", "target": 2, "conditions": [], "changes": []},
+                {"id": 12, "name": "C2", "target": 3, "conditions": [], "changes": []}
+            ]},
+            {"id": 2, "name": "N2", "type": "leaf"},
+            {"id": 3, "name": "N3", "type": "leaf"}
+        ]}
+    }"#;
+
+    const NEW: &str = r#"{
+        "id": "BV17x411w7KC", "name": "NEW", "cover": "", "description": "", "author": "",
+        "variables": [
+            {"id": "$v1", "name": "V1", "type": "normal", "default": 1, "show": true}
+        ],
+        "graph": {"root": 1, "nodes": [
+            {"id": 1, "name": "N1", "type": "choice", "duration": 5000, "default": null, "choices": [
+                {"id": 11, "name": "C1", "target": 2, "changes": [],
+                 "conditions": [{"type": "less_equal", "id": "$v1", "value": 1}]},
+                {"id": 13, "name": "C3", "target": 4, "conditions": [], "changes": []}
+            ]},
+            {"id": 2, "name": "N2", "type": "leaf"},
+            {"id": 4, "name": "N4", "type": "leaf"}
+        ]}
+    }"#;
+
+    #[test]
+    fn test_diff() {
+        let old: Video = serde_json::from_str(OLD).unwrap();
+        let new: Video = serde_json::from_str(NEW).unwrap();
+        let diff = old.diff(&new);
+
+        assert_eq!(
+            diff.metadata,
+            vec![Field {
+                field: "name".to_string(),
+                old: json!("OLD"),
+                new: json!("NEW"),
+            }]
+        );
+
+        assert_eq!(diff.variables.removed, vec!["$v2".to_string()]);
+        assert_eq!(
+            diff.variables.changed,
+            vec![Changed {
+                id: "$v1".to_string(),
+                fields: vec![Field {
+                    field: "default".to_string(),
+                    old: json!(0.),
+                    new: json!(1.),
+                }],
+            }]
+        );
+
+        assert_eq!((diff.nodes.added, diff.nodes.removed), (vec![4], vec![3]));
+        assert_eq!(
+            diff.nodes.changed,
+            vec![Changed {
+                id: 1,
+                fields: vec![Field {
+                    field: "choices".to_string(),
+                    old: json!([11, 12]),
+                    new: json!([11, 13]),
+                }],
+            }]
+        );
+
+        assert_eq!(
+            (diff.choices.added, diff.choices.removed),
+            (vec![13], vec![12])
+        );
+        assert_eq!(
+            diff.choices.changed,
+            vec![Changed {
+                id: 11,
+                fields: vec![Field {
+                    field: "conditions".to_string(),
+                    old: json!(""),
+                    new: json!("$v1 <= 1"),
+                }],
+            }]
+        );
+
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn test_report() {
+        let old: Video = serde_json::from_str(OLD).unwrap();
+        let new: Video = serde_json::from_str(NEW).unwrap();
+        let report = old.diff(&new).to_string();
+
+        assert!(report.contains("元数据:\n  ~ name: \"OLD\" -> \"NEW\"\n"));
+        assert!(report.contains("节点:\n  + 4\n  - 3\n  ~ 1\n      choices: [11,12] -> [11,13]\n"));
+        assert_eq!(old.diff(&old).to_string(), "无差异\n");
+    }
+}
@@ -0,0 +1,254 @@
+//! 互动视频描述文件 (data.json) 格式
+//!
+//! 文件顶层为带格式版本的 [`Document`] 信封, 读取时会自动升级旧版本文件.
+//!
+//! | 格式版本 | 内容 |
+//! | --- | --- |
+//! | 0 | 裸 [`Video`], 无信封 |
+//! | 1 | [`Document`] 信封 |
+//! | 2 | 问题倒计时不限时记为 `null` (原为 0) |
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, info};
+use schemars::{JsonSchema, schema_for};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use thiserror::Error;
+
+use crate::model::Video;
+
+//////// document ////////
+
+/// 当前格式版本
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// 生成文件的工具版本
+pub const TOOL: &str = concat!("bidown ", env!("CARGO_PKG_VERSION"));
+
+/// 互动视频描述文件
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename = "document")]
+pub struct Document {
+    /// 格式版本
+    pub schema: u32,
+    /// 爬取时间 (UNIX 秒), 由旧版本升级而来时缺失
+    pub fetched_at: Option<u64>,
+    /// 爬取时的剧情图版本, 由 [`Document::new`] 取自 `video.version`
+    pub graph_version: Option<usize>,
+    /// 生成文件的工具版本
+    pub tool: String,
+    pub video: Video,
+}
+
+impl Document {
+    /// 以当前时间和工具版本包装描述
+    pub fn new(video: Video) -> Self {
+        let fetched_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok();
+
+        Self {
+            schema: SCHEMA_VERSION,
+            fetched_at,
+            graph_version: video.version,
+            tool: TOOL.to_string(),
+            video,
+        }
+    }
+
+    /// 读取文件, 必要时升级到当前格式版本
+    pub fn from_file(path: &Path) -> crate::Result<Self> {
+        debug!("Loading document at `{}`", path.to_string_lossy());
+        let value: Value = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        Self::from_value(value)
+    }
+
+    /// 从 JSON 值解析, 必要时升级到当前格式版本
+    pub fn from_value(value: Value) -> crate::Result<Self> {
+        Ok(serde_json::from_value(migrate(value)?)?)
+    }
+
+    pub fn to_file(&self, path: &Path) -> crate::Result<()> {
+        debug!("Writing document to `{}`", path.to_string_lossy());
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    /// 生成文件格式的 JSON Schema
+    pub fn json_schema() -> Value {
+        serde_json::to_value(schema_for!(Document)).unwrap_or_default()
+    }
+}
+
+impl Video {
+    /// 读取描述文件, 兼容所有旧格式版本
+    pub fn from_file(path: &Path) -> crate::Result<Self> {
+        Document::from_file(path).map(|d| d.video)
+    }
+
+    /// 以当前格式版本写入描述文件
+    pub fn to_file(&self, path: &Path) -> crate::Result<()> {
+        Document::new(self.clone()).to_file(path)
+    }
+}
+
+//////// migration ////////
+
+/// 升级函数表, 第 k 项将版本 k 升级到版本 k + 1
+const MIGRATIONS: [fn(Value) -> Result<Value>; SCHEMA_VERSION as usize] = [migrate_v0, migrate_v1];
+
+/// 读取格式版本, 无信封时视为版本 0
+fn schema_version(value: &Value) -> Result<u32> {
+    match value.get("schema") {
+        None => Ok(0),
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| v.try_into().ok())
+            .ok_or_else(|| Error::Malformed(format!("格式版本非法: {v}"))),
+    }
+}
+
+/// 逐级升级到当前格式版本
+fn migrate(mut value: Value) -> Result<Value> {
+    let version = schema_version(&value)?;
+    if version > SCHEMA_VERSION {
+        return Err(Error::UnsupportedSchema(version));
+    }
+
+    for (k, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!("Migrating document from schema {k} to {}", k + 1);
+        value = migration(value)?;
+    }
+    Ok(value)
+}
+
+/// 版本 0 -> 1: 包装为信封
+fn migrate_v0(video: Value) -> Result<Value> {
+    if !video.is_object() {
+        return Err(Error::Malformed("顶层不是对象".to_string()));
+    }
+
+    let graph_version = video.get("version").cloned().unwrap_or_default();
+    Ok(json!({
+        "schema": 1,
+        "fetched_at": null,
+        "graph_version": graph_version,
+        "tool": "unknown",
+        "video": video,
+    }))
+}
+
+/// 版本 1 -> 2: 不限时的倒计时由 0 改为 `null`
+fn migrate_v1(mut document: Value) -> Result<Value> {
+    let nodes = document
+        .pointer_mut("/video/graph/nodes")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| Error::Malformed("缺少剧情树节点".to_string()))?;
+    for node in nodes {
+        if let Some(duration) = node.get_mut("duration")
+            && duration.as_u64() == Some(0)
+        {
+            *duration = Value::Null;
+        }
+    }
+
+    document["schema"] = json!(2);
+    Ok(document)
+}
+
+//////// service ////////
+
+/// 描述文件读写的返回类型
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// 描述文件读写的错误类型
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("不支持的格式版本 {0}, 当前工具最高支持 {SCHEMA_VERSION}")]
+    UnsupportedSchema(u32),
+
+    #[error("文件格式错误: {0}")]
+    Malformed(String),
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{Document, Error, SCHEMA_VERSION, migrate};
+
+    use crate::model::NodeConfig;
+
+    fn bare_video() -> serde_json::Value {
+        json!({
+            "id": "BV17x411w7KC", "name": "", "cover": "", "description": "", "author": "",
+            "version": 3,
+            "variables": [],
+            "graph": {"root": 1, "nodes": [{"id": 1, "name": "N1", "type": "leaf"}]}
+        })
+    }
+
+    #[test]
+    fn test_migrate_v0() {
+        let document = Document::from_value(bare_video()).unwrap();
+        assert_eq!(document.schema, SCHEMA_VERSION);
+        assert_eq!(document.fetched_at, None);
+        assert_eq!(document.graph_version, Some(3));
+        assert_eq!(document.video.graph.nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        let choice = |id, duration| {
+            json!({"id": id, "name": "", "type": "choice", "duration": duration,
+                   "default": null, "choices": []})
+        };
+        let mut video = bare_video();
+        video["graph"]["nodes"] = json!([choice(1, 0), choice(2, 5000)]);
+
+        let document = Document::from_value(video).unwrap();
+        let durations: Vec<_> = document
+            .video
+            .graph
+            .nodes
+            .iter()
+            .map(|n| match n.config {
+                NodeConfig::Choice { duration, .. } => duration,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(durations, [None, Some(5000)]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let video = Document::from_value(bare_video()).unwrap().video;
+        let value = serde_json::to_value(Document::new(video)).unwrap();
+        let document = Document::from_value(value).unwrap();
+        assert!(document.fetched_at.is_some());
+        assert_eq!(document.graph_version, Some(3));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let value = json!({"schema": SCHEMA_VERSION + 1});
+        assert!(matches!(migrate(value), Err(Error::UnsupportedSchema(_))));
+    }
+
+    #[test]
+    fn test_published_schema() {
+        // 发布的 schema 须与模型保持一致, 可运行 `cargo run --example schema` 重新生成
+        let published: serde_json::Value =
+            serde_json::from_str(include_str!("../schema/data.schema.json")).unwrap();
+        assert_eq!(published, Document::json_schema());
+    }
+}
@@ -0,0 +1,154 @@
+//! 结构化事件
+//!
+//! 爬取和下载过程中除进度以外的事件 (发现节点, 重试, 警告, 写入文件等),
+//! 在 [`observe`] 的作用域内报告给 [`Observer`], 作用域外不产生任何开销.
+//!
+//! 作用域基于 tokio 任务局部变量, 中间件中的事件 (如限流重试) 也会报告给
+//! 发起请求的操作; 在作用域内 `tokio::spawn` 的任务不继承观察者.
+
+use std::{future::Future, path::PathBuf, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::video::Quality;
+
+//////// event ////////
+
+/// 库操作事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    /// 从已获取节点 `from` 的选项中首次发现节点 `id`
+    NodeDiscovered { id: usize, from: usize },
+    /// 节点信息已获取
+    NodeFetched { id: usize, name: String },
+    /// 已下载且校验通过, 跳过的节点
+    NodeSkipped { id: usize },
+    /// 请求被风控, 退避后第 `attempt` 次重试
+    Retry {
+        host: String,
+        attempt: u32,
+        max: u32,
+        backoff: Duration,
+    },
+    /// 不影响操作继续的问题
+    Warning { message: String },
+    /// 选定节点的视频流
+    StreamSelected {
+        id: usize,
+        quality: Quality,
+        url: String,
+    },
+    /// 写入了文件
+    FileWritten { path: PathBuf, size: u64 },
+}
+
+//////// observer ////////
+
+/// 事件观察者
+///
+/// 闭包 `Fn(&Event)` 和通道发送端均实现了此 trait.
+pub trait Observer: Send + Sync {
+    fn on_event(&self, event: &Event);
+}
+
+impl<F> Observer for F
+where
+    F: Fn(&Event) + Send + Sync,
+{
+    fn on_event(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// 接收端关闭后丢弃事件
+impl Observer for mpsc::UnboundedSender<Event> {
+    fn on_event(&self, event: &Event) {
+        let _ = self.send(event.clone());
+    }
+}
+
+/// 接收端关闭后丢弃事件
+impl Observer for std::sync::mpsc::Sender<Event> {
+    fn on_event(&self, event: &Event) {
+        let _ = self.send(event.clone());
+    }
+}
+
+tokio::task_local! {
+    static OBSERVER: Arc<dyn Observer>;
+}
+
+/// 执行 `future`, 期间的库事件报告给 `observer`
+pub async fn observe<O, F>(observer: O, future: F) -> F::Output
+where
+    O: Observer + 'static,
+    F: Future,
+{
+    OBSERVER.scope(Arc::new(observer), future).await
+}
+
+/// 执行同步操作 `f`, 期间的库事件报告给 `observer`
+pub fn observe_sync<O, R>(observer: O, f: impl FnOnce() -> R) -> R
+where
+    O: Observer + 'static,
+{
+    OBSERVER.sync_scope(Arc::new(observer), f)
+}
+
+/// 报告事件, 不在作用域内时忽略
+pub(crate) fn emit(event: Event) {
+    let _ = OBSERVER.try_with(|observer| observer.on_event(&event));
+}
+
+/// 报告警告
+pub(crate) fn warning(message: impl Into<String>) {
+    emit(Event::Warning {
+        message: message.into(),
+    });
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+
+    use super::{Event, emit, observe, observe_sync, warning};
+
+    #[test]
+    fn test_observe_sync() {
+        let (sender, receiver) = mpsc::channel();
+        emit(Event::NodeSkipped { id: 0 }); // 作用域外
+
+        observe_sync(sender, || {
+            emit(Event::NodeSkipped { id: 1 });
+            warning("w");
+        });
+        emit(Event::NodeSkipped { id: 2 });
+
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            [
+                Event::NodeSkipped { id: 1 },
+                Event::Warning {
+                    message: "w".into()
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_observe() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        observe(sender, async {
+            tokio::task::yield_now().await;
+            emit(Event::NodeSkipped { id: 1 });
+        })
+        .await;
+
+        assert_eq!(receiver.recv().await, Some(Event::NodeSkipped { id: 1 }));
+        assert_eq!(receiver.recv().await, None);
+    }
+}
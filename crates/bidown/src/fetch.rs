@@ -2,19 +2,26 @@
 
 use std::fmt::Debug;
 
+use futures::{Stream, TryStreamExt};
 use log::info;
 use reqwest_middleware::ClientWithMiddleware;
 use thiserror::Error;
 
-use crate::{Progress, model::Video};
+use crate::{
+    Progress,
+    cancel::CancelToken,
+    diff::Diff,
+    id::VideoId,
+    model::{Graph, Node, Video},
+};
 
 //////// module ////////
 
 mod graph;
-use graph::{fetch_graph, fetch_variables};
+use graph::{fetch_graph, fetch_variables, stream_graph};
 
 mod ready;
-use ready::{fetch_metadata, fetch_version};
+use ready::{Metadata, fetch_metadata, fetch_version};
 
 //////// service ////////
 
@@ -28,29 +35,157 @@ pub enum Error {
     Ready(#[from] ready::Error),
 
     #[error("剧情树爬取失败: {0}")]
-    Graph(#[from] graph::Error),
+    Graph(graph::Error),
+
+    #[error("爬取已取消")]
+    Cancelled,
+}
+
+impl From<graph::Error> for Error {
+    fn from(value: graph::Error) -> Self {
+        match value {
+            graph::Error::Cancelled => Self::Cancelled,
+            e => Self::Graph(e),
+        }
+    }
 }
 
 /// 爬取互动视频描述
 impl Video {
-    pub async fn fetch<P>(client: &ClientWithMiddleware, bvid: &str, progress: P) -> Result<Self>
+    pub async fn fetch<P>(
+        client: &ClientWithMiddleware,
+        bvid: &VideoId,
+        progress: P,
+    ) -> Result<Self>
+    where
+        P: FnMut(Progress),
+    {
+        Self::fetch_with_cancel(client, bvid, progress, &CancelToken::new()).await
+    }
+
+    /// 可取消的爬取, 取消后在下一次请求前停止并返回 [`Error::Cancelled`]
+    pub async fn fetch_with_cancel<P>(
+        client: &ClientWithMiddleware,
+        bvid: &VideoId,
+        mut progress: P,
+        cancel: &CancelToken,
+    ) -> Result<Self>
     where
         P: FnMut(Progress),
     {
         info!("Start fetching video `{bvid}`");
+        progress(Progress::Metadata);
 
         // 准备工作
+        let (metadata, root) = cancel
+            .run(fetch_metadata(client, bvid))
+            .await
+            .ok_or(Error::Cancelled)??;
+        let version = cancel
+            .run(fetch_version(client, bvid, root))
+            .await
+            .ok_or(Error::Cancelled)??;
+
+        crawl(client, bvid, metadata, root, version, progress, cancel).await
+    }
+
+    /// 流式爬取, 先获取元数据, 再以流的形式逐个产出节点
+    ///
+    /// 返回的描述中剧情树只有根节点编号, 节点由调用方从流中收集.
+    /// 流只在被轮询时请求下一个节点 (背压), 丢弃流即停止爬取.
+    pub async fn fetch_stream<'a>(
+        client: &'a ClientWithMiddleware,
+        bvid: &'a VideoId,
+    ) -> Result<(Self, impl Stream<Item = Result<Node>> + 'a)> {
+        info!("Start streaming video `{bvid}`");
+
         let (metadata, root) = fetch_metadata(client, bvid).await?;
         let version = fetch_version(client, bvid, root).await?;
-
-        // 构建剧情树
         let (variables, root_eid) = fetch_variables(client, bvid, version).await?;
-        let graph = fetch_graph(client, bvid, root, root_eid, version, progress).await?;
+
+        let graph = Graph {
+            root,
+            nodes: Vec::new(),
+        };
+        let video = metadata.into_video(version, variables, graph);
+        let nodes = stream_graph(client, bvid, root, root_eid, version).map_err(Error::from);
+        Ok((video, nodes))
+    }
+
+    /// 检查剧情图版本, 若已更新则重新爬取, 并给出相对当前描述的差异
+    ///
+    /// 版本未变化时返回 `None`
+    pub async fn update<P>(
+        &self,
+        client: &ClientWithMiddleware,
+        progress: P,
+    ) -> Result<Option<(Self, Diff)>>
+    where
+        P: FnMut(Progress),
+    {
+        self.update_with_cancel(client, progress, &CancelToken::new())
+            .await
+    }
+
+    /// 可取消的更新检查, 取消后返回 [`Error::Cancelled`]
+    pub async fn update_with_cancel<P>(
+        &self,
+        client: &ClientWithMiddleware,
+        mut progress: P,
+        cancel: &CancelToken,
+    ) -> Result<Option<(Self, Diff)>>
+    where
+        P: FnMut(Progress),
+    {
+        let bvid = &self.id;
+        info!("Checking update of video `{bvid}`");
+        progress(Progress::Metadata);
+
+        let (metadata, root) = cancel
+            .run(fetch_metadata(client, bvid))
+            .await
+            .ok_or(Error::Cancelled)??;
+        let version = cancel
+            .run(fetch_version(client, bvid, root))
+            .await
+            .ok_or(Error::Cancelled)??;
+        if self.version == Some(version) {
+            info!("Video `{bvid}` is up to date, version={version}");
+            return Ok(None);
+        }
 
         info!(
-            "Video `{bvid}` fetching done! {} nodes in total",
-            graph.nodes.len()
+            "Video `{bvid}` updated, version={:?} -> {version}",
+            self.version
         );
-        Ok(metadata.into_video(variables, graph))
+        let video = crawl(client, bvid, metadata, root, version, progress, cancel).await?;
+        let diff = self.diff(&video);
+        Ok(Some((video, diff)))
     }
 }
+
+/// 构建剧情树
+async fn crawl<P>(
+    client: &ClientWithMiddleware,
+    bvid: &VideoId,
+    metadata: Metadata,
+    root: usize,
+    version: usize,
+    progress: P,
+    cancel: &CancelToken,
+) -> Result<Video>
+where
+    P: FnMut(Progress),
+{
+    let (variables, root_eid) = cancel
+        .run(fetch_variables(client, bvid, version))
+        .await
+        .ok_or(Error::Cancelled)??;
+    let graph = fetch_graph(client, bvid, root, root_eid, version, progress, cancel).await?;
+
+    info!(
+        "Video `{bvid}` fetching done! {} nodes in total",
+        graph.nodes.len()
+    );
+    Ok(metadata.into_video(version, variables, graph))
+}
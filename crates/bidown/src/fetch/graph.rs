@@ -2,17 +2,23 @@
 
 use std::{collections::HashSet, str::FromStr};
 
+use futures::{Stream, stream};
 use log::{debug, info};
 use reqwest_middleware::ClientWithMiddleware;
 use serde::Deserialize;
+use serde_json::Value;
 use serde_repr::Deserialize_repr;
 use serde_with::{BoolFromInt, serde_as};
 use thiserror::Error;
 
 use crate::{
     Progress,
+    cancel::CancelToken,
+    event::{Event, emit},
+    id::VideoId,
     model::{
-        self, Change, ChangeKind, Condition, ConditionKind, Graph, Node, NodeConfig, VariableConfig,
+        self, Change, ChangeKind, Condition, ConditionKind, Dimension, Graph, Node, NodeConfig,
+        Position, Question, QuestionKind, VariableConfig,
     },
     utils::{Response, one_or_len},
 };
@@ -86,53 +92,103 @@ struct Edge {
     leaf: bool,
     #[serde(rename = "edges", default)]
     config: EdgeConfig,
+    #[serde(rename = "story_list", default)]
+    stories: Vec<Story>,
 }
 
 impl Edge {
     fn into_node(self, id: usize) -> Result<Node> {
-        let Self { name, leaf, config } = self;
+        let Self {
+            name,
+            leaf,
+            config,
+            stories,
+        } = self;
 
         let config = if leaf {
             NodeConfig::Leaf
         } else {
             config.try_into()?
         };
+        let cover = stories
+            .into_iter()
+            .find(|s| s.cid == id && !s.cover.is_empty())
+            .map(|s| s.cover);
 
-        Ok(Node { id, name, config })
+        Ok(Node {
+            id,
+            name,
+            cover,
+            config,
+        })
     }
 }
 
+/// 已经过的节点, 附带缩略图
+#[derive(Debug, Clone, Deserialize)]
+struct Story {
+    cid: usize,
+    #[serde(default)]
+    cover: String,
+}
+
 #[derive(Debug, Clone, Default, Deserialize)]
 struct EdgeConfig {
     #[serde(rename = "questions", default)] // leaf (见下)
     choices: Vec<Choices>,
+    #[serde(default)]
+    dimension: Option<Dimension>,
+    #[serde(default)]
+    skin: Option<Value>,
 }
 
 impl TryFrom<EdgeConfig> for NodeConfig {
     type Error = Error;
 
     fn try_from(value: EdgeConfig) -> Result<Self> {
+        let EdgeConfig {
+            choices,
+            dimension,
+            skin,
+        } = value;
+
         // leaf 时走不到这里
-        one_or_len(value.choices)
+        one_or_len(choices)
             .map_err(Error::ChoicesCount)?
-            .try_into()
+            .into_config(dimension, skin)
     }
 }
 
+#[serde_as]
 #[derive(Debug, Clone, Deserialize)]
 struct Choices {
-    // type = 0
-    duration: isize, // 处理 duration = -1 -> 视为瞬间播放
+    /// 0 为自动跳转, 缺失时视为按钮 (1), 不能视为自动跳转
+    #[serde(rename = "type", default = "Choices::default_kind")]
+    kind: u8,
+    duration: i64, // 毫秒, 不限时为 -1
+    #[serde(rename = "start_time_r", default)]
+    start: isize,
+    #[serde_as(as = "BoolFromInt")]
+    #[serde(rename = "pause_video", default)]
+    pause: bool,
     choices: Vec<Choice>,
 }
 
-impl TryFrom<Choices> for NodeConfig {
-    type Error = Error;
+impl Choices {
+    fn default_kind() -> u8 {
+        1
+    }
 
-    fn try_from(value: Choices) -> Result<Self> {
-        let Choices { duration, choices } = value;
+    fn into_config(self, dimension: Option<Dimension>, skin: Option<Value>) -> Result<NodeConfig> {
+        let Choices {
+            kind,
+            duration,
+            start,
+            pause,
+            choices,
+        } = self;
 
-        let duration = duration.try_into().unwrap_or(0);
+        let duration = u64::try_from(duration).ok().filter(|&d| d > 0);
         let default = Choice::find_default(&choices).map(|c| c.id);
 
         let choices = choices
@@ -140,10 +196,26 @@ impl TryFrom<Choices> for NodeConfig {
             .map(TryInto::try_into)
             .collect::<Result<Vec<model::Choice>>>()?;
 
-        Ok(Self::Choice {
+        // 自动跳转没有界面, 不保留展示信息
+        let kind = match kind {
+            0 => return Ok(NodeConfig::Jump { choices }),
+            2 => QuestionKind::Position,
+            3 => QuestionKind::Hotspot,
+            _ => QuestionKind::Button,
+        };
+        let question = Question {
+            kind,
+            start: start.try_into().unwrap_or(0),
+            pause,
+            dimension,
+            skin,
+        };
+
+        Ok(NodeConfig::Choice {
             duration,
             default,
             choices,
+            question: Some(question),
         })
     }
 }
@@ -163,6 +235,17 @@ struct Choice {
     #[serde_as(as = "BoolFromInt")]
     #[serde(rename = "is_default", default)]
     default: bool,
+    #[serde(default)]
+    x: Option<f64>,
+    #[serde(default)]
+    y: Option<f64>,
+    #[serde(rename = "text_align", default)]
+    align: u8,
+    #[serde(rename = "platform_action", default)]
+    action: String,
+    #[serde_as(as = "BoolFromInt")]
+    #[serde(rename = "is_hidden", default)]
+    hidden: bool,
 }
 
 impl Choice {
@@ -181,12 +264,18 @@ impl TryFrom<Choice> for model::Choice {
             target,
             conditions,
             changes,
+            x,
+            y,
+            align,
+            action,
+            hidden,
             ..
         } = value;
 
         let conditions =
             Condition::from_str(&conditions).ok_or_else(|| Error::Condition(conditions))?;
         let changes = Change::from_str(&changes).ok_or_else(|| Error::Change(changes))?;
+        let position = x.zip(y).map(|(x, y)| Position { x, y, align });
 
         Ok(Self {
             id,
@@ -194,6 +283,9 @@ impl TryFrom<Choice> for model::Choice {
             target,
             conditions,
             changes,
+            position,
+            action,
+            hidden,
         })
     }
 }
@@ -274,7 +366,7 @@ impl Change {
                             strip_with_tag!(s.trim_start(), ('+', true), ('-', false))?;
 
                         let value = {
-                            let value = f64::from_str(value).ok()?;
+                            let value = f64::from_str(value.trim_start()).ok()?;
                             if add_kind { value } else { -value }
                         };
 
@@ -312,7 +404,7 @@ impl Node {
 impl NodeConfig {
     fn list_edges(&self) -> Vec<Target> {
         match self {
-            Self::Choice { choices, .. } => choices
+            Self::Choice { choices, .. } | Self::Jump { choices } => choices
                 .iter()
                 .map(|model::Choice { id, target, .. }| Target {
                     cid: *target,
@@ -344,12 +436,15 @@ pub enum Error {
 
     #[error("隐藏值更改语句非法: {0}")]
     Change(String),
+
+    #[error("爬取已取消")]
+    Cancelled,
 }
 
 /// 爬取变量列表
 pub async fn fetch_variables(
     client: &ClientWithMiddleware,
-    bvid: &str,
+    bvid: &VideoId,
     version: usize,
 ) -> Result<(Vec<model::Variable>, usize)> {
     let url =
@@ -363,7 +458,7 @@ pub async fn fetch_variables(
 /// 获取并解析节点 (边) 信息
 async fn fetch_node(
     client: &ClientWithMiddleware,
-    bvid: &str,
+    bvid: &VideoId,
     cid: usize,
     eid: usize,
     version: usize,
@@ -377,54 +472,125 @@ async fn fetch_node(
     edge.into_node(cid)
 }
 
+/// 深度优先爬取状态, 每次调用 [`Crawl::next`] 获取一个新节点
+struct Crawl<'a> {
+    client: &'a ClientWithMiddleware,
+    bvid: &'a VideoId,
+    version: usize,
+    /// 已获取的节点
+    visit: HashSet<usize>,
+    /// 已获取的和待访问边界中的节点
+    discovered: HashSet<usize>,
+    stack: Vec<Target>,
+}
+
+impl<'a> Crawl<'a> {
+    fn new(
+        client: &'a ClientWithMiddleware,
+        bvid: &'a VideoId,
+        root: usize,
+        root_eid: usize,
+        version: usize,
+    ) -> Self {
+        Self {
+            client,
+            bvid,
+            version,
+            visit: HashSet::new(),
+            discovered: HashSet::from([root]),
+            stack: vec![Target {
+                cid: root,
+                eid: root_eid,
+            }],
+        }
+    }
+
+    /// 获取下一个节点, 全部获取后返回 `None`
+    async fn next(&mut self) -> Result<Option<Node>> {
+        while let Some(Target { cid, eid }) = self.stack.pop() {
+            if !self.visit.insert(cid) {
+                // 标记为已获取
+                continue;
+            }
+
+            let node = fetch_node(self.client, self.bvid, cid, eid, self.version).await?;
+            info!("Node `{}` fetched, name=`{}`", node.id, node.name);
+            emit(Event::NodeFetched {
+                id: node.id,
+                name: node.name.clone(),
+            });
+
+            let mut edges = node.list_edges();
+            for Target { cid, .. } in &edges {
+                if self.discovered.insert(*cid) {
+                    emit(Event::NodeDiscovered {
+                        id: *cid,
+                        from: node.id,
+                    });
+                }
+            }
+            self.stack.append(&mut edges); // 推入邻边
+            return Ok(Some(node));
+        }
+        Ok(None)
+    }
+}
+
 /// 爬取剧情图
 pub async fn fetch_graph<P>(
     client: &ClientWithMiddleware,
-    bvid: &str,
+    bvid: &VideoId,
     root: usize,
     root_eid: usize,
     version: usize,
     mut progress: P,
+    cancel: &CancelToken,
 ) -> Result<Graph>
 where
     P: FnMut(Progress),
 {
-    let mut visit: HashSet<usize> = HashSet::new();
+    let mut crawl = Crawl::new(client, bvid, root, root_eid, version);
     let mut nodes = Vec::new(); // 其实可以预先计算容量的说 (
 
-    let mut stack = vec![Target {
-        cid: root,
-        eid: root_eid,
-    }];
-    while let Some(Target { cid, eid }) = stack.pop() {
-        if !visit.insert(cid) {
-            // 标记为已获取
-            continue;
-        }
-
-        let node = fetch_node(client, bvid, cid, eid, version).await?;
-        info!("Node `{}` fetched, name=`{}`", node.id, node.name);
-        progress(Progress {
-            current: nodes.len(),
-            total: usize::MAX,
+    while let Some(node) = cancel.run(crawl.next()).await.ok_or(Error::Cancelled)?? {
+        progress(Progress::Crawl {
+            current: nodes.len() + 1,
+            estimated: crawl.discovered.len(),
             id: node.id,
             name: node.name.clone(),
         });
-
-        stack.append(&mut node.list_edges()); // 推入邻边
         nodes.push(node);
     }
 
     Ok(Graph { root, nodes })
 }
 
+/// 以流的形式爬取剧情图, 按获取顺序产出节点
+///
+/// 只在流被轮询时请求下一个节点, 出错后流结束.
+pub fn stream_graph<'a>(
+    client: &'a ClientWithMiddleware,
+    bvid: &'a VideoId,
+    root: usize,
+    root_eid: usize,
+    version: usize,
+) -> impl Stream<Item = Result<Node>> + 'a {
+    let crawl = Crawl::new(client, bvid, root, root_eid, version);
+    stream::try_unfold(crawl, async |mut crawl| {
+        Ok(crawl.next().await?.map(|node| (node, crawl)))
+    })
+}
+
 //////// test ////////
 
 #[cfg(test)]
 mod test {
-    use super::{/*Edge, Response,*/ Variable};
+    use super::{Edge, Variable};
 
-    use crate::model::{self, Change, ChangeKind, Condition, ConditionKind, VariableConfig};
+    use crate::model::{
+        self, Change, ChangeKind, Condition, ConditionKind, NodeConfig, Position, QuestionKind,
+        VariableConfig,
+    };
 
     #[test]
     fn test_variable_deserialize() {
@@ -460,11 +626,85 @@ mod test {
         );
     }
 
-    // #[test]
-    // fn test_edge_deserialize() {
-    //     let text = r#"{}"#;
-    //     let _response: Response<Edge> = serde_json::from_str(text).unwrap();
-    // }
+    #[test]
+    fn test_edge_deserialize() {
+        let text = r#"{
+            "title": "N1", "edges": {
+                "dimension": {"width": 1920, "height": 1080, "rotate": 0, "sar": 1},
+                "questions": [{
+                    "id": 1, "type": 2, "start_time_r": 5000, "duration": -1, "pause_video": 1,
+                    "choices": [{
+                        "id": 11, "platform_action": "JUMP 11 2", "native_action": "",
+                        "condition": "", "cid": 2, "x": 100, "y": 200, "text_align": 2,
+                        "option": "C1", "is_default": 1
+                    }]
+                }],
+                "skin": {"title_text_color": "FFFFFF"}
+            },
+            "story_list": [{"cid": 1, "cover": "https://...jpg"}]
+        }"#;
+        let node = serde_json::from_str::<Edge>(text)
+            .unwrap()
+            .into_node(1)
+            .unwrap();
+        assert_eq!(node.cover.as_deref(), Some("https://...jpg"));
+
+        let NodeConfig::Choice {
+            duration,
+            default,
+            choices,
+            question: Some(question),
+        } = node.config
+        else {
+            panic!("expected choice node with question");
+        };
+        assert_eq!((duration, default), (None, Some(11)));
+        assert_eq!(question.kind, QuestionKind::Position);
+        assert_eq!((question.start, question.pause), (5000, true));
+        assert_eq!(question.dimension.map(|d| d.width), Some(1920.));
+        assert!(question.skin.is_some());
+        assert_eq!(
+            choices[0].position,
+            Some(Position {
+                x: 100.,
+                y: 200.,
+                align: 2
+            })
+        );
+        assert_eq!(choices[0].action, "JUMP 11 2");
+        assert!(!choices[0].hidden);
+    }
+
+    #[test]
+    fn test_edge_without_type() {
+        let text = r#"{
+            "title": "N1", "edges": {
+                "questions": [{
+                    "id": 1, "duration": 5000,
+                    "choices": [{
+                        "id": 11, "platform_action": "JUMP 11 2", "native_action": "",
+                        "condition": "", "cid": 2, "option": "C1"
+                    }]
+                }]
+            }
+        }"#;
+        let node = serde_json::from_str::<Edge>(text)
+            .unwrap()
+            .into_node(1)
+            .unwrap();
+
+        // 缺失 type 的选项按按钮处理, 而不是自动跳转
+        let NodeConfig::Choice {
+            duration,
+            question: Some(question),
+            ..
+        } = node.config
+        else {
+            panic!("expected choice node with question");
+        };
+        assert_eq!(question.kind, QuestionKind::Button);
+        assert_eq!(duration, Some(5000));
+    }
 
     #[test]
     fn test_conditions_deserialize() {
@@ -505,4 +745,45 @@ mod test {
             ])
         );
     }
+
+    #[test]
+    fn test_expression_display() {
+        let conditions = Condition::from_str("$v1<=1.00 && $v2>2.50").unwrap();
+        let conditions: Vec<_> = conditions.iter().map(|c| c.to_string()).collect();
+        assert_eq!(conditions, ["$v1 <= 1", "$v2 > 2.5"]);
+
+        let changes = Change::from_str("$v1=1.00;$v2=$v2+0.50;$v3=$v3-2").unwrap();
+        let changes: Vec<_> = changes.iter().map(|c| c.to_string()).collect();
+        assert_eq!(changes, ["$v1 = 1", "$v2 = $v2 + 0.5", "$v3 = $v3 - 2"]);
+
+        // 输出可以被重新解析
+        assert_eq!(
+            Change::from_str(&changes.join(";")).map(|c| c.iter().map(|c| c.value).collect()),
+            Some(vec![1., 0.5, -2.])
+        );
+    }
+
+    #[test]
+    fn test_changes_whitespace() {
+        assert_eq!(
+            Change::from_str("$v1= 1;$v2=$v2+ 0.50;$v3 = $v3 -  2"),
+            Some(vec![
+                Change {
+                    kind: ChangeKind::Set,
+                    id: "$v1".to_string(),
+                    value: 1.
+                },
+                Change {
+                    kind: ChangeKind::Add,
+                    id: "$v2".to_string(),
+                    value: 0.5
+                },
+                Change {
+                    kind: ChangeKind::Add,
+                    id: "$v3".to_string(),
+                    value: -2.
+                }
+            ])
+        );
+    }
 }
@@ -6,6 +6,7 @@ use serde::Deserialize;
 use thiserror::Error;
 
 use crate::{
+    id::VideoId,
     model::{Graph, Variable, Video},
     utils::Response,
 };
@@ -15,7 +16,7 @@ use crate::{
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct Metadata {
     #[serde(rename = "bvid")]
-    id: String,
+    id: VideoId,
     #[serde(rename = "cid")]
     root: usize,
     #[serde(rename = "title")]
@@ -28,7 +29,7 @@ pub struct Metadata {
 }
 
 impl Metadata {
-    pub fn into_video(self, variables: Vec<Variable>, graph: Graph) -> Video {
+    pub fn into_video(self, version: usize, variables: Vec<Variable>, graph: Graph) -> Video {
         let Self {
             id,
             name,
@@ -37,7 +38,7 @@ impl Metadata {
             owner,
             ..
         } = self;
-        let Owner { name: author } = owner;
+        let Owner { name: author, face } = owner;
 
         Video {
             id,
@@ -45,6 +46,8 @@ impl Metadata {
             cover,
             description,
             author,
+            avatar: (!face.is_empty()).then_some(face),
+            version: Some(version),
             variables,
             graph,
         }
@@ -54,6 +57,8 @@ impl Metadata {
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct Owner {
     pub name: String,
+    #[serde(default)]
+    pub face: String,
 }
 
 //////// version ////////
@@ -88,7 +93,7 @@ pub enum Error {
 /// 爬取元数据和根节点 cid
 pub async fn fetch_metadata(
     client: &ClientWithMiddleware,
-    bvid: &str,
+    bvid: &VideoId,
 ) -> Result<(Metadata, usize)> {
     let url = format!("https://api.bilibili.com/x/web-interface/view?bvid={bvid}");
     debug!("Fetching metadata from `{url}`");
@@ -99,7 +104,11 @@ pub async fn fetch_metadata(
 }
 
 /// 爬取互动视频版本信息
-pub async fn fetch_version(client: &ClientWithMiddleware, bvid: &str, cid: usize) -> Result<usize> {
+pub async fn fetch_version(
+    client: &ClientWithMiddleware,
+    bvid: &VideoId,
+    cid: usize,
+) -> Result<usize> {
     let url = format!("https://api.bilibili.com/x/player/v2?cid={cid}&bvid={bvid}");
     debug!("Fetching graph version from `{url}`");
     let response = client.get(url).send().await?;
@@ -109,22 +118,26 @@ pub async fn fetch_version(client: &ClientWithMiddleware, bvid: &str, cid: usize
 
 #[cfg(test)]
 mod test {
-    use crate::fetch::ready::{Metadata, Owner};
+    use crate::{
+        fetch::ready::{Metadata, Owner},
+        id::VideoId,
+    };
 
     #[test]
     fn test_metadata_deserialize() {
         assert_eq!(
             serde_json::from_str::<Metadata>(
-                r#"{"bvid":"BV..","pic":"https://...jpg","title":"VIDEO_TITLE","desc":"VIDEO_DESCRIPTION","owner":{"name":"VIDEO_AUTHOR"},"cid":1}"#
+                r#"{"bvid":"BV17x411w7KC","pic":"https://...jpg","title":"VIDEO_TITLE","desc":"VIDEO_DESCRIPTION","owner":{"name":"VIDEO_AUTHOR","face":"https://...png"},"cid":1}"#
             ).unwrap(),
             Metadata {
-                id: "BV..".to_string(),
+                id: VideoId::from_bvid("BV17x411w7KC").unwrap(),
                 root: 1,
                 name: "VIDEO_TITLE".to_string(),
                 cover: "https://...jpg".to_string(),
                 description: "VIDEO_DESCRIPTION".to_string(),
                 owner: Owner {
-                    name: "VIDEO_AUTHOR".to_string()
+                    name: "VIDEO_AUTHOR".to_string(),
+                    face: "https://...png".to_string(),
                 }
             }
         );
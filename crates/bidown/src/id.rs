@@ -0,0 +1,289 @@
+//! 视频标识解析
+//!
+//! 支持 BV 号, av 号, 视频页 URL (含查询参数) 以及 b23.tv 短链.
+
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use log::debug;
+use reqwest::Url;
+use reqwest_middleware::ClientWithMiddleware;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+//////// algorithm ////////
+
+const XOR_CODE: u64 = 23442827791579;
+const MASK_CODE: u64 = 2251799813685247;
+const MAX_AID: u64 = 1 << 51;
+const ALPHABET: &[u8; 58] = b"FcwAPNKTMug3GV5Lj7EJnHpWsx4tb8haYeviqBz6rkCy12mUSDQX9RdoZf";
+const ENCODE_MAP: [usize; 9] = [8, 7, 0, 5, 1, 3, 2, 4, 6];
+const BASE: u64 = 58;
+const PREFIX: &str = "BV1";
+
+/// av 号转 BV 号
+fn av2bv(aid: u64) -> String {
+    let mut bvid = [0u8; 9];
+    let mut tmp = (MAX_AID | aid) ^ XOR_CODE;
+    for i in ENCODE_MAP {
+        bvid[i] = ALPHABET[(tmp % BASE) as usize];
+        tmp /= BASE;
+    }
+    // ALPHABET 只含 ASCII
+    bvid.iter().fold(PREFIX.to_string(), |mut s, &c| {
+        s.push(c as char);
+        s
+    })
+}
+
+/// BV 号转 av 号, 格式非法时返回 `None`
+fn bv2av(bvid: &str) -> Option<u64> {
+    let body = bvid.strip_prefix(PREFIX)?.as_bytes();
+    if body.len() != ENCODE_MAP.len() {
+        return None;
+    }
+
+    let mut tmp = 0;
+    for &i in ENCODE_MAP.iter().rev() {
+        let index = ALPHABET.iter().position(|&c| c == body[i])?;
+        tmp = tmp * BASE + index as u64;
+    }
+    Some((tmp & MASK_CODE) ^ XOR_CODE)
+}
+
+//////// id ////////
+
+/// 视频标识
+///
+/// 内部保存规范化的 BV 号, 序列化为字符串, 反序列化时校验.
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(try_from = "String", into = "String")]
+pub struct VideoId(String);
+
+impl VideoId {
+    /// 从 BV 号构造 (前缀大小写不敏感)
+    pub fn from_bvid(bvid: &str) -> Result<Self> {
+        let bvid = match bvid.get(..2) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("BV") => format!("BV{}", &bvid[2..]),
+            _ => return Err(Error::Invalid(bvid.to_string())),
+        };
+        match bv2av(&bvid) {
+            Some(_) => Ok(Self(bvid)),
+            None => Err(Error::Invalid(bvid)),
+        }
+    }
+
+    /// 从 av 号构造
+    pub fn from_aid(aid: u64) -> Result<Self> {
+        if aid == 0 || aid >= MAX_AID {
+            return Err(Error::Invalid(format!("av{aid}")));
+        }
+        Ok(Self(av2bv(aid)))
+    }
+
+    pub fn bvid(&self) -> &str {
+        &self.0
+    }
+
+    pub fn aid(&self) -> u64 {
+        // 构造时已校验
+        bv2av(&self.0).unwrap_or_default()
+    }
+
+    /// 离线解析 BV 号, av 号或视频页 URL
+    ///
+    /// # Notes
+    ///
+    /// - b23.tv 短链需要联网, 请使用 [`VideoId::resolve`]
+    pub fn parse(input: &str) -> Result<Self> {
+        let input = input.trim();
+        if let Some(id) = Self::parse_plain(input) {
+            return id;
+        }
+
+        let url = parse_url(input)?;
+        match url.host_str() {
+            Some(host) if is_short_host(host) => Err(Error::ShortLink(input.to_string())),
+            Some(_) => Self::parse_url(&url).ok_or_else(|| Error::Invalid(input.to_string())),
+            None => Err(Error::Invalid(input.to_string())),
+        }
+    }
+
+    /// 解析任意输入, b23.tv 短链将跟随重定向解析
+    pub async fn resolve(client: &ClientWithMiddleware, input: &str) -> Result<Self> {
+        match Self::parse(input) {
+            Err(Error::ShortLink(link)) => {
+                let url = parse_url(&link)?;
+                debug!("Resolving short link `{url}`");
+                let response = client.get(url).send().await?;
+                let target = response.url();
+                debug!("Short link `{link}` redirected to `{target}`");
+                Self::parse_url(target).ok_or(Error::UnresolvedLink(link))
+            }
+            result => result,
+        }
+    }
+
+    /// 解析纯 BV 号或 av 号, 不是这两种格式时返回 `None`
+    fn parse_plain(input: &str) -> Option<Result<Self>> {
+        let prefix = input.get(..2)?;
+        if prefix.eq_ignore_ascii_case("BV") {
+            Some(Self::from_bvid(input))
+        } else if prefix.eq_ignore_ascii_case("av") {
+            let aid = input[2..]
+                .parse()
+                .map_err(|_| Error::Invalid(input.to_string()));
+            Some(aid.and_then(Self::from_aid))
+        } else {
+            None
+        }
+    }
+
+    /// 从视频页 URL 的路径或查询参数中提取标识
+    fn parse_url(url: &Url) -> Option<Self> {
+        let from_path = url
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .find_map(|s| Self::parse_plain(s)?.ok());
+        let from_query = || {
+            url.query_pairs().find_map(|(k, v)| match k.as_ref() {
+                "bvid" => Self::from_bvid(&v).ok(),
+                "aid" | "avid" => Self::from_aid(v.parse().ok()?).ok(),
+                _ => None,
+            })
+        };
+        from_path.or_else(from_query)
+    }
+}
+
+fn is_short_host(host: &str) -> bool {
+    matches!(host, "b23.tv" | "www.b23.tv" | "bili2233.cn")
+}
+
+fn parse_url(input: &str) -> Result<Url> {
+    let input = if input.contains("://") {
+        input.to_string()
+    } else {
+        format!("https://{input}")
+    };
+    Url::parse(&input).map_err(|_| Error::Invalid(input))
+}
+
+impl FromStr for VideoId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<String> for VideoId {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        Self::from_bvid(&value)
+    }
+}
+
+impl From<VideoId> for String {
+    fn from(value: VideoId) -> Self {
+        value.0
+    }
+}
+
+impl Display for VideoId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for VideoId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+//////// service ////////
+
+/// 标识解析的返回类型
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// 标识解析的错误类型
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    ReqwestMiddleware(#[from] reqwest_middleware::Error),
+
+    #[error("无法识别的视频标识: `{0}`")]
+    Invalid(String),
+
+    #[error("短链需要联网解析: `{0}`")]
+    ShortLink(String),
+
+    #[error("短链未重定向到视频页: `{0}`")]
+    UnresolvedLink(String),
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use super::{Error, VideoId, av2bv, bv2av};
+
+    #[test]
+    fn test_av_bv_convert() {
+        assert_eq!(av2bv(170001), "BV17x411w7KC");
+        assert_eq!(bv2av("BV17x411w7KC"), Some(170001));
+        assert_eq!(av2bv(111298867365120), "BV1L9Uoa9EUx");
+        assert_eq!(bv2av("BV1L9Uoa9EUx"), Some(111298867365120));
+    }
+
+    #[test]
+    fn test_parse() {
+        let id = VideoId::from_bvid("BV17x411w7KC").unwrap();
+
+        for input in [
+            "BV17x411w7KC",
+            "bv17x411w7KC",
+            "av170001",
+            " AV170001 ",
+            "https://www.bilibili.com/video/BV17x411w7KC?p=1&t=10",
+            "www.bilibili.com/video/av170001/",
+            "https://m.bilibili.com/video/BV17x411w7KC",
+            "https://www.bilibili.com/blackboard/player.html?bvid=BV17x411w7KC",
+        ] {
+            assert_eq!(VideoId::parse(input).unwrap(), id, "input: `{input}`");
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(matches!(
+            VideoId::parse("https://b23.tv/abcdef"),
+            Err(Error::ShortLink(_))
+        ));
+        assert!(matches!(VideoId::parse("BV0"), Err(Error::Invalid(_))));
+        assert!(matches!(VideoId::parse("av0"), Err(Error::Invalid(_))));
+        assert!(matches!(
+            VideoId::parse("https://www.bilibili.com/"),
+            Err(Error::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let id: VideoId = serde_json::from_str(r#""BV17x411w7KC""#).unwrap();
+        assert_eq!(id.aid(), 170001);
+        assert_eq!(serde_json::to_string(&id).unwrap(), r#""BV17x411w7KC""#);
+
+        // 只接受 BV 号
+        assert!(serde_json::from_str::<VideoId>(r#""BV0""#).is_err());
+        assert!(serde_json::from_str::<VideoId>(r#""av170001""#).is_err());
+    }
+}
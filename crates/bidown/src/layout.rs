@@ -0,0 +1,334 @@
+//! 剧情图分层布局
+//!
+//! 简化的 Sugiyama 布局, 自上而下排列:
+//!
+//! 1. 按根节点出发的 BFS 深度分层, 不可达的节点放在最后一层
+//!
+//! 2. 跨越多层的边插入虚拟节点, 按重心法交替上下扫描减少交叉
+//!
+//! 3. 逐层从左到右排布并居中, 边经过虚拟节点折线连接
+//!
+//! 指向同层或更浅层的边 (回边) 不参与排序, 直接连接两端.
+
+use std::collections::{HashMap, VecDeque, hash_map::Entry};
+
+use serde::Serialize;
+
+use crate::model::Graph;
+
+//////// metric ////////
+
+/// 节点宽度
+pub const NODE_WIDTH: f64 = 160.;
+/// 节点高度
+pub const NODE_HEIGHT: f64 = 48.;
+/// 虚拟节点 (长边经过的点) 占用的宽度
+const DUMMY_WIDTH: f64 = 16.;
+/// 同层节点间距
+const NODE_GAP: f64 = 32.;
+/// 层间距
+const LAYER_GAP: f64 = 80.;
+/// 重心法扫描轮数
+const SWEEPS: usize = 4;
+
+//////// model ////////
+
+/// 节点位置, 坐标为左上角
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Placement {
+    pub id: usize,
+    pub layer: usize,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// 边 (选项) 的折线路径
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Route {
+    pub choice: usize,
+    pub from: usize,
+    pub to: usize,
+    pub points: Vec<(f64, f64)>,
+    /// 是否为回边 (指向同层或更浅层)
+    pub back: bool,
+}
+
+/// 剧情图布局
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Layout {
+    pub nodes: Vec<Placement>,
+    pub edges: Vec<Route>,
+    pub width: f64,
+    pub height: f64,
+}
+
+//////// layered graph ////////
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Vertex {
+    Node(usize),
+    /// 第 `edge` 条边上的第 `k` 个虚拟节点
+    Dummy {
+        edge: usize,
+        k: usize,
+    },
+}
+
+impl Vertex {
+    fn width(&self) -> f64 {
+        match self {
+            Self::Node(_) => NODE_WIDTH,
+            Self::Dummy { .. } => DUMMY_WIDTH,
+        }
+    }
+}
+
+/// 按 BFS 深度分层
+fn assign_layers(graph: &Graph) -> (HashMap<usize, usize>, Vec<usize>) {
+    let mut layers = HashMap::new();
+    let mut order = Vec::with_capacity(graph.nodes.len());
+    let nodes = graph.nodes_map();
+
+    let mut queue = VecDeque::from([(graph.root, 0)]);
+    while let Some((id, layer)) = queue.pop_front() {
+        let Some(node) = nodes.get(&id) else {
+            continue;
+        };
+        if layers.contains_key(&id) {
+            continue;
+        }
+        layers.insert(id, layer);
+        order.push(id);
+        for choice in node.config.choices() {
+            queue.push_back((choice.target, layer + 1));
+        }
+    }
+
+    // 不可达的节点
+    let last = layers.values().max().map_or(0, |l| l + 1);
+    for node in &graph.nodes {
+        if let Entry::Vacant(entry) = layers.entry(node.id) {
+            entry.insert(last);
+            order.push(node.id);
+        }
+    }
+    (layers, order)
+}
+
+/// 重心: 相邻层邻居位置的平均值
+fn barycenter(neighbors: &[Vertex], position: &HashMap<Vertex, usize>) -> Option<f64> {
+    let positions: Vec<f64> = neighbors
+        .iter()
+        .filter_map(|v| position.get(v).map(|&p| p as f64))
+        .collect();
+    (!positions.is_empty()).then(|| positions.iter().sum::<f64>() / positions.len() as f64)
+}
+
+impl Graph {
+    /// 计算分层布局
+    pub fn layout(&self) -> Layout {
+        let (layer_of, discovery) = assign_layers(self);
+        let depth = layer_of.values().max().map_or(0, |l| l + 1);
+
+        // 初始顺序为发现顺序
+        let mut layers: Vec<Vec<Vertex>> = vec![Vec::new(); depth];
+        for &id in &discovery {
+            layers[layer_of[&id]].push(Vertex::Node(id));
+        }
+
+        // 收集边, 为长边插入虚拟节点
+        let mut edges = Vec::new();
+        let mut up: HashMap<Vertex, Vec<Vertex>> = HashMap::new();
+        let mut down: HashMap<Vertex, Vec<Vertex>> = HashMap::new();
+        for node in &self.nodes {
+            for choice in node.config.choices() {
+                let Some(&to_layer) = layer_of.get(&choice.target) else {
+                    continue; // 目标不存在
+                };
+                let from_layer = layer_of[&node.id];
+                let edge = edges.len();
+                edges.push((choice.id, node.id, choice.target, from_layer < to_layer));
+                if from_layer >= to_layer {
+                    continue;
+                }
+
+                let mut previous = Vertex::Node(node.id);
+                for (k, layer) in (from_layer + 1..to_layer).enumerate() {
+                    let dummy = Vertex::Dummy { edge, k };
+                    layers[layer].push(dummy);
+                    down.entry(previous).or_default().push(dummy);
+                    up.entry(dummy).or_default().push(previous);
+                    previous = dummy;
+                }
+                let target = Vertex::Node(choice.target);
+                down.entry(previous).or_default().push(target);
+                up.entry(target).or_default().push(previous);
+            }
+        }
+
+        // 重心法减少交叉
+        let positions = |layer: &[Vertex]| -> HashMap<Vertex, usize> {
+            layer.iter().enumerate().map(|(k, &v)| (v, k)).collect()
+        };
+        let reorder = |layer: &mut Vec<Vertex>, fixed: &[Vertex], links: &HashMap<_, Vec<_>>| {
+            let fixed = positions(fixed);
+            let mut keyed: Vec<(f64, Vertex)> = layer
+                .iter()
+                .enumerate()
+                .map(|(k, v)| {
+                    let neighbors = links.get(v).map(Vec::as_slice).unwrap_or_default();
+                    (barycenter(neighbors, &fixed).unwrap_or(k as f64), *v)
+                })
+                .collect();
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            *layer = keyed.into_iter().map(|(_, v)| v).collect();
+        };
+        for _ in 0..SWEEPS {
+            for l in 1..depth {
+                let (fixed, rest) = layers.split_at_mut(l);
+                reorder(&mut rest[0], &fixed[l - 1], &up);
+            }
+            for l in (0..depth.saturating_sub(1)).rev() {
+                let (rest, fixed) = layers.split_at_mut(l + 1);
+                reorder(&mut rest[l], &fixed[0], &down);
+            }
+        }
+
+        // 排布坐标, 每层居中
+        let layer_width = |layer: &[Vertex]| -> f64 {
+            let widths: f64 = layer.iter().map(Vertex::width).sum();
+            widths + NODE_GAP * layer.len().saturating_sub(1) as f64
+        };
+        let width = layers.iter().map(|l| layer_width(l)).fold(0., f64::max);
+        let height = depth as f64 * (NODE_HEIGHT + LAYER_GAP) - LAYER_GAP;
+
+        let mut placed: HashMap<Vertex, (f64, f64)> = HashMap::new();
+        for (l, layer) in layers.iter().enumerate() {
+            let y = l as f64 * (NODE_HEIGHT + LAYER_GAP);
+            let mut x = (width - layer_width(layer)) / 2.;
+            for v in layer {
+                placed.insert(*v, (x, y));
+                x += v.width() + NODE_GAP;
+            }
+        }
+
+        let nodes = discovery
+            .iter()
+            .map(|&id| {
+                let (x, y) = placed[&Vertex::Node(id)];
+                Placement {
+                    id,
+                    layer: layer_of[&id],
+                    x,
+                    y,
+                }
+            })
+            .collect();
+
+        // 边从起点底部中央经过虚拟节点到终点顶部中央
+        let bottom = |v: &Vertex| {
+            let (x, y) = placed[v];
+            (x + v.width() / 2., y + NODE_HEIGHT)
+        };
+        let top = |v: &Vertex| {
+            let (x, y) = placed[v];
+            (x + v.width() / 2., y)
+        };
+        let edges = edges
+            .into_iter()
+            .enumerate()
+            .map(|(edge, (choice, from, to, forward))| {
+                let mut points = vec![bottom(&Vertex::Node(from))];
+                if forward {
+                    let span = layer_of[&to] - layer_of[&from] - 1;
+                    for k in 0..span {
+                        let dummy = Vertex::Dummy { edge, k };
+                        points.push(top(&dummy));
+                        points.push(bottom(&dummy));
+                    }
+                }
+                points.push(top(&Vertex::Node(to)));
+                Route {
+                    choice,
+                    from,
+                    to,
+                    points,
+                    back: !forward,
+                }
+            })
+            .collect();
+
+        Layout {
+            nodes,
+            edges,
+            width,
+            height: height.max(0.),
+        }
+    }
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use super::{NODE_HEIGHT, NODE_WIDTH};
+
+    use crate::model::Video;
+
+    #[test]
+    fn test_layout() {
+        let video: Video = serde_json::from_str(
+            r#"{
+            "id": "BV17x411w7KC", "name": "", "cover": "", "description": "", "author": "",
+            "variables": [],
+            "graph": {"root": 1, "nodes": [
+                {"id": 1, "name": "N1", "type": "choice", "duration": null, "default": null, "choices": [
+                    {"id": 11, "name": "", "target": 2, "conditions": [], "changes": []},
+                    {"id": 12, "name": "", "target": 3, "conditions": [], "changes": []},
+                    {"id": 13, "name": "", "target": 4, "conditions": [], "changes": []}
+                ]},
+                {"id": 2, "name": "N2", "type": "jump", "choices": [
+                    {"id": 21, "name": "", "target": 3, "conditions": [], "changes": []}
+                ]},
+                {"id": 3, "name": "N3", "type": "jump", "choices": [
+                    {"id": 31, "name": "", "target": 4, "conditions": [], "changes": []},
+                    {"id": 32, "name": "", "target": 1, "conditions": [], "changes": []}
+                ]},
+                {"id": 4, "name": "N4", "type": "leaf"},
+                {"id": 5, "name": "N5", "type": "leaf"}
+            ]}
+        }"#,
+        )
+        .unwrap();
+        let layout = video.graph.layout();
+
+        let layer = |id| layout.nodes.iter().find(|n| n.id == id).unwrap().layer;
+        assert_eq!([1, 2, 3, 4, 5].map(layer), [0, 1, 1, 1, 2]);
+
+        // 同层节点不重叠
+        for a in &layout.nodes {
+            for b in &layout.nodes {
+                if a.id != b.id && a.layer == b.layer {
+                    assert!((a.x - b.x).abs() >= NODE_WIDTH);
+                }
+            }
+        }
+        assert!(
+            layout
+                .nodes
+                .iter()
+                .all(|n| n.x + NODE_WIDTH <= layout.width)
+        );
+        assert!(
+            layout
+                .nodes
+                .iter()
+                .all(|n| n.y + NODE_HEIGHT <= layout.height)
+        );
+
+        let edge = |choice| layout.edges.iter().find(|e| e.choice == choice).unwrap();
+        assert!(!edge(11).back && edge(11).points.len() == 2);
+        assert!(edge(21).back && edge(32).back);
+        assert_eq!(layout.edges.len(), 6);
+    }
+}
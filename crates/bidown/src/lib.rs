@@ -4,13 +4,28 @@ use thiserror::Error;
 
 //////// module ////////
 
+pub mod cache;
+pub mod cancel;
+pub mod diff;
+pub mod document;
+pub mod event;
 pub mod fetch;
+pub mod id;
+pub mod layout;
+pub mod limit;
 pub mod model;
+pub mod package;
+pub mod pipeline;
+pub mod player;
+pub mod runtime;
+pub mod simulate;
 pub mod solve;
+pub mod subtitle;
 mod utils;
 pub mod video;
 
-pub use utils::Progress;
+pub use cancel::CancelToken;
+pub use utils::{Progress, Transfer};
 
 //////// error ////////
 
@@ -20,9 +35,24 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// bidown 统合错误类型
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error(transparent)]
+    Document(#[from] document::Error),
+
     #[error(transparent)]
     Fetch(#[from] fetch::Error),
 
+    #[error(transparent)]
+    Id(#[from] id::Error),
+
+    #[error(transparent)]
+    Package(#[from] package::Error),
+
+    #[error(transparent)]
+    Runtime(#[from] runtime::Error),
+
+    #[error(transparent)]
+    Simulate(#[from] simulate::Error),
+
     #[error(transparent)]
     Solve(#[from] solve::Error),
 
@@ -36,3 +66,13 @@ pub enum Error {
     #[error(transparent)]
     Io(#[from] io::Error),
 }
+
+impl Error {
+    /// 是否由取消令牌引起
+    pub fn is_cancelled(&self) -> bool {
+        matches!(
+            self,
+            Self::Fetch(fetch::Error::Cancelled) | Self::Video(video::Error::Cancelled)
+        )
+    }
+}
@@ -0,0 +1,302 @@
+//! 客户端限流中间件
+//!
+//! 按 host 分别维护令牌桶, 请求前附加随机等待;
+//! 遇到风控 (HTTP 412 或业务码 -352) 时自动退避并重试.
+//!
+//! 中间件内部状态可以共享: 同一个客户端上的爬取和下载共用一套令牌桶,
+//! 多个客户端之间可以通过 `ClientBuilder::with_arc` 共享同一个 [`RateLimit`].
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use http::Extensions;
+use log::{debug, warn};
+use reqwest::{Request, Response, StatusCode, header::CONTENT_TYPE};
+use reqwest_middleware::{Middleware, Next};
+use tokio::time::sleep;
+
+use crate::{
+    event::{Event, emit, warning},
+    utils::{jitter, rebuild_response, response_code},
+};
+
+//////// policy ////////
+
+/// 风控业务码
+const RISK_CODE: i64 = -352;
+
+/// 限流策略
+///
+/// # Notes
+///
+/// - `rate` 须为正数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Policy {
+    /// 每秒补充的令牌数
+    pub rate: f64,
+    /// 令牌桶容量 (允许的突发请求数)
+    pub burst: f64,
+    /// 每次请求前附加的随机等待上限
+    pub jitter: Duration,
+    /// 首次被风控时的退避时长, 此后连续被风控则翻倍
+    pub backoff: Duration,
+    /// 退避时长上限
+    pub max_backoff: Duration,
+    /// 被风控后的最大重试次数
+    pub max_retries: u32,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            rate: 2.,
+            burst: 4.,
+            jitter: Duration::from_millis(250),
+            backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(160),
+            max_retries: 4,
+        }
+    }
+}
+
+//////// bucket ////////
+
+/// 单个 host 的令牌桶
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+    blocked: Option<Instant>,
+    backoff: Option<Duration>,
+}
+
+impl Bucket {
+    fn new(policy: &Policy, now: Instant) -> Self {
+        Self {
+            tokens: policy.burst,
+            last: now,
+            blocked: None,
+            backoff: None,
+        }
+    }
+
+    /// 预订一个令牌, 返回需要等待的时长
+    ///
+    /// 令牌可以透支, 以便并发请求依次排队.
+    fn acquire(&mut self, policy: &Policy, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * policy.rate).min(policy.burst) - 1.;
+        self.last = now;
+
+        let wait = if self.tokens >= 0. {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / policy.rate)
+        };
+        let blocked = self
+            .blocked
+            .map(|t| t.saturating_duration_since(now))
+            .unwrap_or_default();
+        wait.max(blocked)
+    }
+
+    /// 记录一次风控, 返回退避时长
+    fn ban(&mut self, policy: &Policy, now: Instant) -> Duration {
+        let backoff = self
+            .backoff
+            .map_or(policy.backoff, |b| (b * 2).min(policy.max_backoff));
+        self.backoff = Some(backoff);
+        self.blocked = Some(now + backoff);
+        self.tokens = self.tokens.min(0.);
+        backoff
+    }
+
+    /// 记录一次正常响应, 重置退避
+    fn pass(&mut self) {
+        self.backoff = None;
+    }
+}
+
+//////// middleware ////////
+
+/// 令牌桶限流中间件
+#[derive(Debug, Default)]
+pub struct RateLimit {
+    policy: Policy,
+    hosts: HashMap<String, Policy>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimit {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// 为指定 host 单独设置策略
+    pub fn with_host(mut self, host: impl Into<String>, policy: Policy) -> Self {
+        self.hosts.insert(host.into(), policy);
+        self
+    }
+
+    fn policy(&self, host: &str) -> &Policy {
+        self.hosts.get(host).unwrap_or(&self.policy)
+    }
+
+    fn with_bucket<T>(&self, host: &str, f: impl FnOnce(&mut Bucket, &Policy, Instant) -> T) -> T {
+        let policy = self.policy(host);
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets
+            .entry(host.to_string())
+            .or_insert_with(|| Bucket::new(policy, now));
+        f(bucket, policy, now)
+    }
+
+    fn acquire(&self, host: &str) -> Duration {
+        self.with_bucket(host, Bucket::acquire) + jitter(self.policy(host).jitter)
+    }
+
+    fn ban(&self, host: &str) -> Duration {
+        self.with_bucket(host, Bucket::ban)
+    }
+
+    fn pass(&self, host: &str) {
+        self.with_bucket(host, |bucket, _, _| bucket.pass())
+    }
+}
+
+/// 检查响应是否被风控
+///
+/// JSON 响应会被缓冲以读取业务码, 其余响应原样返回.
+async fn inspect(response: Response) -> reqwest_middleware::Result<(Response, bool)> {
+    if response.status() == StatusCode::PRECONDITION_FAILED {
+        return Ok((response, true));
+    }
+
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("json"));
+    if !is_json {
+        return Ok((response, false));
+    }
+
+    let url = response.url().clone();
+    let status = response.status();
+    let version = response.version();
+    let headers = response.headers().clone();
+    let body = response.bytes().await?;
+
+    let risk = response_code(&body) == Some(RISK_CODE);
+    Ok((rebuild_response(url, status, version, headers, body), risk))
+}
+
+#[async_trait]
+impl Middleware for RateLimit {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let host = req.url().host_str().unwrap_or_default().to_string();
+        let max_retries = self.policy(&host).max_retries;
+
+        let mut retries = 0;
+        loop {
+            let wait = self.acquire(&host);
+            if !wait.is_zero() {
+                debug!("Waiting {wait:?} before requesting `{host}`");
+                sleep(wait).await;
+            }
+
+            let retry = (retries < max_retries).then(|| req.try_clone()).flatten();
+            let response = next.clone().run(req, extensions).await?;
+            let (response, banned) = inspect(response).await?;
+
+            if !banned {
+                self.pass(&host);
+                return Ok(response);
+            }
+
+            let backoff = self.ban(&host);
+            match retry {
+                Some(r) => {
+                    retries += 1;
+                    warn!(
+                        "Rate limited by `{host}`, backing off for {backoff:?} ({retries}/{max_retries})"
+                    );
+                    emit(Event::Retry {
+                        host: host.clone(),
+                        attempt: retries,
+                        max: max_retries,
+                        backoff,
+                    });
+                    req = r;
+                }
+                None => {
+                    warn!("Rate limited by `{host}`, giving up after {retries} retries");
+                    warning(format!("`{host}` 持续风控, 重试 {retries} 次后放弃"));
+                    return Ok(response);
+                }
+            }
+        }
+    }
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{Bucket, Policy};
+
+    #[test]
+    fn test_bucket_acquire() {
+        let policy = Policy {
+            rate: 2.,
+            burst: 2.,
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let mut bucket = Bucket::new(&policy, now);
+
+        // 突发额度内无需等待
+        assert_eq!(bucket.acquire(&policy, now), Duration::ZERO);
+        assert_eq!(bucket.acquire(&policy, now), Duration::ZERO);
+        // 透支后依次排队
+        assert_eq!(bucket.acquire(&policy, now), Duration::from_millis(500));
+        assert_eq!(bucket.acquire(&policy, now), Duration::from_millis(1000));
+        // 补充令牌
+        let later = now + Duration::from_secs(3);
+        assert_eq!(bucket.acquire(&policy, later), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_bucket_backoff() {
+        let policy = Policy {
+            backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(25),
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let mut bucket = Bucket::new(&policy, now);
+
+        assert_eq!(bucket.ban(&policy, now), Duration::from_secs(10));
+        assert_eq!(bucket.acquire(&policy, now), Duration::from_secs(10));
+        assert_eq!(bucket.ban(&policy, now), Duration::from_secs(20));
+        assert_eq!(bucket.ban(&policy, now), Duration::from_secs(25));
+
+        bucket.pass();
+        assert_eq!(bucket.ban(&policy, now), Duration::from_secs(10));
+    }
+}
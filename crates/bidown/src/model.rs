@@ -1,34 +1,36 @@
 //! 互动视频描述数据结构
 
-use std::{fs::File, io::BufReader, path::Path};
+use std::fmt::{Display, Formatter};
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::impl_pareq_with_id;
+use crate::{id::VideoId, impl_pareq_with_id};
 
 /// 互动视频描述
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename = "video")]
 pub struct Video {
     // metadata
-    pub id: String,
+    pub id: VideoId,
     pub name: String,
     pub cover: String,
     pub description: String,
     pub author: String,
+    /// UP 主头像 URL, 旧数据中可能缺失
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+    /// 爬取时的剧情图版本 (graph_version), 旧数据中可能缺失
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<usize>,
     // execution
     pub variables: Vec<Variable>,
     pub graph: Graph,
 }
 
-impl Video {
-    pub fn from_file(path: &Path) -> crate::Result<Self> {
-        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
-    }
-}
-
 /// 变量声明
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename = "variable")]
 pub struct Variable {
     pub id: String,
@@ -37,14 +39,14 @@ pub struct Variable {
     pub config: VariableConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum VariableConfig {
     Normal { default: f64, show: bool },
     Random,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename = "graph")]
 pub struct Graph {
     pub root: usize,
@@ -52,11 +54,14 @@ pub struct Graph {
 }
 
 /// 剧情节点
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename = "node")]
 pub struct Node {
     pub id: usize,
     pub name: String,
+    /// 节点缩略图 URL, 旧数据中可能缺失
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
     #[serde(flatten)]
     pub config: NodeConfig,
 }
@@ -67,13 +72,22 @@ impl Node {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum NodeConfig {
+    /// 由用户选择的问题, 包括按钮和隐藏热区
     Choice {
-        duration: usize,
+        /// 倒计时 (毫秒), `None` 时不限时
+        duration: Option<u64>,
         default: Option<usize>,
         choices: Vec<Choice>,
+        /// 问题的展示方式, 旧数据中缺失
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        question: Option<Question>,
+    },
+    /// 自动跳转: 视频结束时按顺序判定, 进入第一个满足条件的选项
+    Jump {
+        choices: Vec<Choice>,
     },
     Leaf,
 }
@@ -82,10 +96,61 @@ impl NodeConfig {
     pub fn is_leaf(&self) -> bool {
         matches!(self, Self::Leaf)
     }
+
+    /// 节点的全部选项, 叶子节点为空
+    pub fn choices(&self) -> &[Choice] {
+        match self {
+            Self::Choice { choices, .. } | Self::Jump { choices } => choices,
+            Self::Leaf => &[],
+        }
+    }
+}
+
+/// 问题的展示方式
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename = "question")]
+pub struct Question {
+    #[serde(rename = "type")]
+    pub kind: QuestionKind,
+    /// 选项出现的时间 (毫秒)
+    pub start: u64,
+    /// 出现选项时是否暂停视频
+    pub pause: bool,
+    /// 坐标选项参照的视频尺寸
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimension: Option<Dimension>,
+    /// 选项皮肤, 原样保存
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skin: Option<Value>,
+}
+
+/// 问题类型, 自动跳转 (接口取值 0) 见 [`NodeConfig::Jump`]
+///
+/// | 枚举项 | 含义 | 接口取值 |
+/// | --- | --- | --- |
+/// | Button | 底部按钮 | 1 |
+/// | Position | 坐标按钮 | 2 |
+/// | Hotspot | 视频中的隐藏热区 | 3 |
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestionKind {
+    #[default]
+    Button,
+    Position,
+    Hotspot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename = "dimension")]
+pub struct Dimension {
+    pub width: f64,
+    pub height: f64,
+    #[serde(default)]
+    pub rotate: f64,
 }
 
 /// 剧情节点选项
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename = "choice")]
 pub struct Choice {
     // metadata
@@ -95,9 +160,30 @@ pub struct Choice {
     // execution
     pub conditions: Vec<Condition>,
     pub changes: Vec<Change>,
+    // layout (旧数据中缺失)
+    /// 坐标按钮的位置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    /// 平台动作, 如 `JUMP {edge} {cid}`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub action: String,
+    /// 是否为隐藏选项
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+}
+
+/// 选项位置, 相对于 [`Question::dimension`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename = "position")]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+    /// 文字对齐方式, 原样保存接口取值
+    #[serde(default)]
+    pub align: u8,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename = "condition")]
 pub struct Condition {
     #[serde(rename = "type")]
@@ -106,7 +192,7 @@ pub struct Condition {
     pub value: f64,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ConditionKind {
     Equal,
@@ -117,7 +203,26 @@ pub enum ConditionKind {
     GreaterEqual,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.id, self.kind, self.value)
+    }
+}
+
+impl Display for ConditionKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterEqual => ">=",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename = "change")]
 pub struct Change {
     #[serde(rename = "type")]
@@ -126,13 +231,24 @@ pub struct Change {
     pub value: f64,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ChangeKind {
     Set,
     Add,
 }
 
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Self { kind, id, value } = self;
+        match kind {
+            ChangeKind::Set => write!(f, "{id} = {value}"),
+            ChangeKind::Add if *value < 0. => write!(f, "{id} = {id} - {}", -value),
+            ChangeKind::Add => write!(f, "{id} = {id} + {value}"),
+        }
+    }
+}
+
 //////// equal ////////
 
 impl_pareq_with_id!(Video);
@@ -0,0 +1,480 @@
+//! 互动视频打包
+//!
+//! 包为 zip 文件, 收录下载目录中的描述文件, 全部节点视频和图片资源,
+//! 并附带记录大小与 SHA-256 的清单 `manifest.json`.
+//!
+//! 下载目录布局:
+//!
+//! | 路径 | 内容 |
+//! | --- | --- |
+//! | `data.json` | 描述文件 |
+//! | `video/{id}.mp4` | 节点视频 |
+//! | `cover.{ext}` | 封面 (可选) |
+//! | `avatar.{ext}` | UP 主头像 (可选) |
+//! | `thumbnail/{id}.{ext}` | 节点缩略图 (可选) |
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use zip::{CompressionMethod, ZipArchive, ZipWriter, result::ZipError, write::SimpleFileOptions};
+
+use crate::{
+    document::{Document, TOOL},
+    id::VideoId,
+    model::Video,
+    utils::sha256,
+};
+
+//////// layout ////////
+
+/// 描述文件路径
+pub const DATA_FILE: &str = "data.json";
+
+/// 节点视频目录
+pub const VIDEO_DIR: &str = "video";
+
+/// 清单文件路径
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// 封面文件名 (不含扩展名)
+pub const COVER_STEM: &str = "cover";
+
+/// 头像文件名 (不含扩展名)
+pub const AVATAR_STEM: &str = "avatar";
+
+/// 节点缩略图目录
+pub const THUMBNAIL_DIR: &str = "thumbnail";
+
+/// 当前包格式版本
+pub const PACKAGE_FORMAT: u32 = 1;
+
+/// 节点视频在下载目录 (或包) 中的相对路径
+pub fn video_path(id: usize) -> String {
+    format!("{VIDEO_DIR}/{id}.mp4")
+}
+
+/// 节点缩略图在下载目录 (或包) 中的相对路径 (不含扩展名)
+pub fn thumbnail_stem(id: usize) -> String {
+    format!("{THUMBNAIL_DIR}/{id}")
+}
+
+/// 相对路径 (以 `/` 分隔) 转为本地路径
+fn local_path(root: &Path, path: &str) -> PathBuf {
+    path.split('/').fold(root.to_path_buf(), |p, s| p.join(s))
+}
+
+//////// manifest ////////
+
+/// 清单条目
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// 相对路径, 以 `/` 分隔
+    pub path: String,
+    pub size: u64,
+    /// SHA-256 (十六进制小写)
+    pub sha256: String,
+}
+
+impl Entry {
+    fn from_file(root: &Path, path: String) -> Result<Self> {
+        let (size, sha256) = sha256(&mut File::open(local_path(root, &path))?)?;
+        Ok(Self { path, size, sha256 })
+    }
+}
+
+/// 图片资源的相对路径, 缺失的资源不记录
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Assets {
+    pub cover: Option<String>,
+    pub avatar: Option<String>,
+    /// 节点 id -> 缩略图
+    pub thumbnails: BTreeMap<usize, String>,
+}
+
+impl Assets {
+    /// 在下载目录中查找资源
+    pub fn scan(dir: &Path, video: &Video) -> io::Result<Self> {
+        let mut thumbnails = BTreeMap::new();
+        for node in &video.graph.nodes {
+            if let Some(path) = find_stem(dir, &thumbnail_stem(node.id))? {
+                thumbnails.insert(node.id, path);
+            }
+        }
+
+        Ok(Self {
+            cover: find_stem(dir, COVER_STEM)?,
+            avatar: find_stem(dir, AVATAR_STEM)?,
+            thumbnails,
+        })
+    }
+
+    /// 全部资源路径
+    pub fn paths(&self) -> impl Iterator<Item = &String> {
+        self.cover
+            .iter()
+            .chain(&self.avatar)
+            .chain(self.thumbnails.values())
+    }
+}
+
+/// 包清单
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    pub tool: String,
+    pub id: VideoId,
+    pub name: String,
+    pub entries: Vec<Entry>,
+    /// 旧包中缺失
+    #[serde(default)]
+    pub assets: Assets,
+}
+
+/// 校验报告
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Report {
+    /// 清单中有, 包中没有的文件
+    pub missing: Vec<String>,
+    /// 大小或校验和不符, 或无法读取的文件
+    pub corrupt: Vec<String>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.corrupt.is_empty()
+    }
+}
+
+//////// package ////////
+
+/// 互动视频包
+pub struct Package<R> {
+    archive: ZipArchive<R>,
+    manifest: Manifest,
+}
+
+impl Package<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self> {
+        debug!("Opening package `{}`", path.to_string_lossy());
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// 将下载目录打包到 `out`
+    ///
+    /// # Notes
+    ///
+    /// - 每个节点的视频都必须存在, 图片资源可选
+    pub fn create(dir: &Path, out: &Path) -> crate::Result<Manifest> {
+        let video = Video::from_file(&dir.join(DATA_FILE))?;
+        info!(
+            "Packing video `{}` from `{}`",
+            video.id,
+            dir.to_string_lossy()
+        );
+
+        // 收集文件
+        let assets = Assets::scan(dir, &video)?;
+        let mut paths = vec![DATA_FILE.to_string()];
+        paths.extend(assets.paths().cloned());
+        for node in &video.graph.nodes {
+            let path = video_path(node.id);
+            if !local_path(dir, &path).is_file() {
+                return Err(Error::MissingFile(path).into());
+            }
+            paths.push(path);
+        }
+
+        let entries = paths
+            .into_iter()
+            .map(|p| Entry::from_file(dir, p))
+            .collect::<Result<Vec<_>>>()?;
+        let manifest = Manifest {
+            format: PACKAGE_FORMAT,
+            tool: TOOL.to_string(),
+            id: video.id,
+            name: video.name,
+            entries,
+            assets,
+        };
+
+        // 写入压缩包, 视频本身已压缩, 直接存储
+        let mut writer = ZipWriter::new(BufWriter::new(File::create(out)?));
+        let stored = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(true);
+        let deflated = SimpleFileOptions::default();
+
+        for Entry { path, .. } in &manifest.entries {
+            debug!("Packing `{path}`");
+            let options = if path.ends_with(".json") {
+                deflated
+            } else {
+                stored
+            };
+            writer
+                .start_file(path.as_str(), options)
+                .map_err(Error::from)?;
+            io::copy(&mut File::open(local_path(dir, path))?, &mut writer)?;
+        }
+
+        writer
+            .start_file(MANIFEST_FILE, deflated)
+            .map_err(Error::from)?;
+        serde_json::to_writer_pretty(&mut writer, &manifest)?;
+        writer.finish().map_err(Error::from)?.flush()?;
+
+        info!(
+            "Package of video `{}` created at `{}`, {} files in total",
+            manifest.id,
+            out.to_string_lossy(),
+            manifest.entries.len()
+        );
+        Ok(manifest)
+    }
+}
+
+/// 按相对路径 `stem` (不含扩展名) 查找下载目录中的文件
+fn find_stem(dir: &Path, stem: &str) -> io::Result<Option<String>> {
+    let (parent, name) = stem.rsplit_once('/').unwrap_or(("", stem));
+    let Ok(entries) = fs::read_dir(local_path(dir, parent)) else {
+        return Ok(None);
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_file() || path.file_stem().and_then(|s| s.to_str()) != Some(name) {
+            continue;
+        }
+        if let Some(file) = path.file_name().and_then(|s| s.to_str()) {
+            return Ok(Some(match parent {
+                "" => file.to_string(),
+                parent => format!("{parent}/{file}"),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+impl<R: Read + Seek> Package<R> {
+    pub fn from_reader(reader: R) -> Result<Self> {
+        let mut archive = ZipArchive::new(reader)?;
+        let manifest = match archive.by_name(MANIFEST_FILE) {
+            Ok(file) => serde_json::from_reader(file)?,
+            Err(ZipError::FileNotFound) => return Err(Error::ManifestNotFound),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { archive, manifest })
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// 列出包中的文件
+    pub fn entries(&self) -> &[Entry] {
+        &self.manifest.entries
+    }
+
+    /// 读取包中的互动视频描述
+    pub fn video(&mut self) -> crate::Result<Video> {
+        let file = self.archive.by_name(DATA_FILE).map_err(Error::from)?;
+        let value: Value = serde_json::from_reader(file)?;
+        Ok(Document::from_value(value)?.video)
+    }
+
+    /// 按清单校验包中的每个文件
+    pub fn verify(&mut self) -> Result<Report> {
+        let mut report = Report::default();
+
+        for Entry {
+            path,
+            size,
+            sha256: hash,
+        } in &self.manifest.entries
+        {
+            let mut file = match self.archive.by_name(path) {
+                Ok(file) => file,
+                Err(ZipError::FileNotFound) => {
+                    warn!("Packed file `{path}` is missing");
+                    report.missing.push(path.clone());
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            // 读取失败 (如 CRC 错误) 同样视为损坏
+            let valid = sha256(&mut file).is_ok_and(|(s, h)| s == *size && h == *hash);
+            if !valid {
+                warn!("Packed file `{path}` is corrupt");
+                report.corrupt.push(path.clone());
+            }
+        }
+
+        info!(
+            "Package of video `{}` verified, {} missing, {} corrupt",
+            self.manifest.id,
+            report.missing.len(),
+            report.corrupt.len()
+        );
+        Ok(report)
+    }
+
+    /// 解包到 `dir`, 恢复下载目录布局 (含清单)
+    pub fn extract(&mut self, dir: &Path) -> Result<()> {
+        info!(
+            "Extracting package of video `{}` to `{}`",
+            self.manifest.id,
+            dir.to_string_lossy()
+        );
+
+        let paths = self.manifest.entries.iter().map(|e| e.path.as_str());
+        for path in paths.chain([MANIFEST_FILE]) {
+            let mut file = self.archive.by_name(path)?;
+            let name = file
+                .enclosed_name()
+                .ok_or_else(|| Error::UnsafePath(path.to_string()))?;
+
+            let target = dir.join(name);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            debug!("Extracting `{path}`");
+            let mut writer = BufWriter::new(File::create(target)?);
+            io::copy(&mut file, &mut writer)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+//////// service ////////
+
+/// 打包过程的返回类型
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// 打包过程的错误类型
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Zip(#[from] ZipError),
+
+    #[error("缺少文件: `{0}`")]
+    MissingFile(String),
+
+    #[error("包中缺少清单")]
+    ManifestNotFound,
+
+    #[error("包中文件路径不安全: `{0}`")]
+    UnsafePath(String),
+
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use std::{fs, io::Cursor};
+
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    use super::{DATA_FILE, MANIFEST_FILE, Package, video_path};
+
+    fn prepare(dir: &std::path::Path) {
+        let video = json!({
+            "id": "BV17x411w7KC", "name": "NAME", "cover": "", "description": "", "author": "",
+            "variables": [],
+            "graph": {"root": 1, "nodes": [
+                {"id": 1, "name": "N1", "type": "choice", "duration": null, "default": null, "choices": [
+                    {"id": 11, "name": "C1", "target": 2, "conditions": [], "changes": []}
+                ]},
+                {"id": 2, "name": "N2", "type": "leaf"}
+            ]}
+        });
+        fs::write(dir.join(DATA_FILE), video.to_string()).unwrap();
+        fs::create_dir(dir.join("video")).unwrap();
+        fs::write(dir.join(video_path(1)), b"node 1").unwrap();
+        fs::write(dir.join(video_path(2)), b"node 2").unwrap();
+        fs::write(dir.join("cover.jpg"), b"cover").unwrap();
+        fs::write(dir.join("avatar.png"), b"avatar").unwrap();
+        fs::create_dir(dir.join("thumbnail")).unwrap();
+        fs::write(dir.join("thumbnail/2.webp"), b"thumbnail 2").unwrap();
+    }
+
+    #[test]
+    fn test_create_verify_extract() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir(&source).unwrap();
+        prepare(&source);
+
+        let out = dir.path().join("video.zip");
+        let manifest = Package::create(&source, &out).unwrap();
+        let paths: Vec<_> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                DATA_FILE,
+                "cover.jpg",
+                "avatar.png",
+                "thumbnail/2.webp",
+                "video/1.mp4",
+                "video/2.mp4"
+            ]
+        );
+        assert_eq!(manifest.assets.thumbnails[&2], "thumbnail/2.webp");
+
+        let mut package = Package::open(&out).unwrap();
+        assert_eq!(package.manifest().name, "NAME");
+        assert_eq!(package.video().unwrap().graph.nodes.len(), 2);
+        assert!(package.verify().unwrap().is_ok());
+
+        let target = dir.path().join("target");
+        package.extract(&target).unwrap();
+        assert_eq!(fs::read(target.join(video_path(2))).unwrap(), b"node 2");
+        assert!(target.join(MANIFEST_FILE).is_file());
+    }
+
+    #[test]
+    fn test_missing_node() {
+        let dir = tempdir().unwrap();
+        prepare(dir.path());
+        fs::remove_file(dir.path().join(video_path(2))).unwrap();
+
+        assert!(Package::create(dir.path(), &dir.path().join("video.zip")).is_err());
+    }
+
+    #[test]
+    fn test_verify_corrupt() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir(&source).unwrap();
+        prepare(&source);
+
+        let out = dir.path().join("video.zip");
+        Package::create(&source, &out).unwrap();
+
+        // 篡改存储 (未压缩) 的节点视频
+        let mut bytes = fs::read(&out).unwrap();
+        let at = bytes.windows(6).position(|w| w == b"node 2").unwrap();
+        bytes[at + 5] = b'3';
+
+        let mut package = Package::from_reader(Cursor::new(bytes)).unwrap();
+        let report = package.verify().unwrap();
+        assert_eq!(report.corrupt, ["video/2.mp4"]);
+        assert!(report.missing.is_empty());
+    }
+}
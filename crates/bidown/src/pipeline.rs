@@ -0,0 +1,396 @@
+//! 流水线下载
+//!
+//! 爬取剧情树的同时下载已获取的节点视频, 以传输时间掩盖爬取请求的延迟.
+//! 目录结构与 [`Video::download_with`] 相同.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::Path,
+    pin::pin,
+};
+
+use futures::{Stream, StreamExt, stream::FuturesUnordered};
+use log::{info, warn};
+use reqwest_middleware::ClientWithMiddleware;
+use tokio::sync::mpsc;
+
+use crate::{
+    Progress, Result, Transfer,
+    cancel::CancelToken,
+    event::{Event, emit, warning},
+    fetch,
+    id::VideoId,
+    model::{Graph, Node, Video},
+    package::{DATA_FILE, VIDEO_DIR},
+    video::{self, Checksum, Checksums, DownloadOptions, Overwrite, download},
+};
+
+impl Graph {
+    /// 是否已爬取完整: 根节点和全部选项的目标节点均已获取
+    pub fn is_complete(&self) -> bool {
+        let fetched: HashSet<usize> = self.nodes.iter().map(|n| n.id).collect();
+        fetched.contains(&self.root)
+            && self
+                .nodes
+                .iter()
+                .flat_map(|n| n.config.choices())
+                .all(|c| fetched.contains(&c.target))
+    }
+}
+
+impl Video {
+    /// 从节点流 (见 [`Video::fetch_stream`]) 收集剧情树, 同时按选项下载到打包目录 `dir`
+    ///
+    /// 节点及其选项的目标节点一经发现即加入下载队列 (下载只需要节点编号),
+    /// 同时下载的节点数不超过 `options.concurrency`;
+    /// 节点流结束且节点视频下载完成后, 再下载图片资源, 字幕和弹幕.
+    /// `progress` 交替报告爬取和节点视频下载进度, 下载的总节点数随爬取推进而修正.
+    ///
+    /// 单个节点下载失败时记录警告并继续, 返回下载失败的节点 (升序).
+    pub async fn download_stream<S, P>(
+        &mut self,
+        client: &ClientWithMiddleware,
+        nodes: S,
+        dir: &Path,
+        options: &DownloadOptions,
+        mut progress: P,
+        cancel: &CancelToken,
+    ) -> Result<Vec<usize>>
+    where
+        S: Stream<Item = fetch::Result<Node>>,
+        P: FnMut(Progress),
+    {
+        let bvid = self.id.clone();
+        let path = dir.join(VIDEO_DIR);
+        info!("Start pipelined downloading of video `{bvid}` with {options:?}");
+        fs::create_dir_all(&path)?;
+
+        let resume = options.overwrite == Overwrite::Resume;
+        let mut checksums = Checksums::load(&path)?;
+
+        // 各节点的传输进度经由通道汇总
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        // 续传时先检查已有文件, 可以沿用时不下载; 失败时带上节点编号
+        let start = |id: usize, recorded: Option<Checksum>| {
+            let sender = sender.clone();
+            let file = path.join(format!("{id}.mp4"));
+            let bvid = &bvid;
+            async move {
+                if resume {
+                    let existing = video::check_existing(
+                        client,
+                        &file,
+                        bvid,
+                        id,
+                        options.quality,
+                        recorded.as_ref(),
+                    )
+                    .await
+                    .map_err(|e| (id, e))?;
+                    if let Some(checksum) = existing {
+                        return Ok((id, checksum, true));
+                    }
+                }
+                download(client, &file, bvid, id, options.quality, |transfer| {
+                    let _ = sender.send((id, transfer));
+                })
+                .await
+                .map(|checksum| (id, checksum, false))
+                .map_err(|e| (id, e))
+            }
+        };
+
+        let mut nodes = pin!(nodes);
+        let mut crawling = true;
+        let mut names: HashMap<usize, String> = HashMap::new();
+        let mut discovered: HashSet<usize> = HashSet::new();
+        let mut skipped = 0;
+        let mut failed = Vec::new();
+
+        let mut pending = VecDeque::new();
+        let mut downloads = FuturesUnordered::new();
+        let mut transfers: HashMap<usize, Transfer> = HashMap::new();
+        let mut current = 0;
+
+        loop {
+            while downloads.len() < options.concurrency.max(1)
+                && let Some(id) = pending.pop_front()
+            {
+                downloads.push(start(id, checksums.0.get(&id).cloned()));
+            }
+            if !crawling && downloads.is_empty() {
+                break;
+            }
+
+            let total = discovered.len() - skipped;
+            let name = |id: usize| names.get(&id).cloned().unwrap_or_default();
+            tokio::select! {
+                biased;
+                _ = cancel.cancelled() => return Err(video::Error::Cancelled.into()),
+                Some((id, transfer)) = receiver.recv() => {
+                    transfers.insert(id, transfer.clone());
+                    progress(Progress::Download {
+                        current,
+                        total,
+                        id,
+                        name: name(id),
+                        transfer,
+                    });
+                }
+                Some(result) = downloads.next(), if !downloads.is_empty() => {
+                    let (id, checksum, intact) = match result {
+                        Ok(downloaded) => downloaded,
+                        Err((id, e)) => {
+                            warn!("Failed to download node `{id}` of `{bvid}`: {e}");
+                            warning(format!("节点 {id} 下载失败: {e}"));
+                            transfers.remove(&id);
+                            failed.push(id);
+                            continue;
+                        }
+                    };
+                    if checksums.0.get(&id) != Some(&checksum) {
+                        checksums.0.insert(id, checksum);
+                        checksums.save(&path)?;
+                    }
+                    if intact {
+                        emit(Event::NodeSkipped { id });
+                        skipped += 1;
+                        continue;
+                    }
+
+                    // 完成前发出的传输进度已在通道中
+                    while let Ok((id, transfer)) = receiver.try_recv() {
+                        transfers.insert(id, transfer);
+                    }
+                    current += 1;
+                    progress(Progress::Download {
+                        current,
+                        total,
+                        id,
+                        name: name(id),
+                        transfer: transfers.remove(&id).unwrap_or_default(),
+                    });
+                }
+                node = nodes.next(), if crawling => {
+                    let Some(node) = node else {
+                        let count = self.graph.nodes.len();
+                        info!("Video `{bvid}` crawling done, {count} nodes in total");
+                        crawling = false;
+                        continue;
+                    };
+                    let node = node?;
+                    let id = node.id;
+
+                    // 下载只需要节点编号, 发现即加入队列
+                    let targets = node.config.choices().iter().map(|c| c.target);
+                    for target in std::iter::once(id).chain(targets) {
+                        if discovered.insert(target) {
+                            pending.push_back(target);
+                        }
+                    }
+                    progress(Progress::Crawl {
+                        current: self.graph.nodes.len() + 1,
+                        estimated: discovered.len(),
+                        id,
+                        name: node.name.clone(),
+                    });
+                    names.insert(id, node.name.clone());
+                    self.graph.nodes.push(node);
+                }
+            }
+        }
+
+        if options.assets {
+            cancel
+                .run(self.download_assets(client, dir))
+                .await
+                .ok_or(video::Error::Cancelled)??;
+        }
+        cancel
+            .run(self.download_extras(client, &path, options.extras, |_| ()))
+            .await
+            .ok_or(video::Error::Cancelled)??;
+
+        failed.sort_unstable();
+        if failed.is_empty() {
+            info!(
+                "Video `{bvid}` fetching done! See at `{}`",
+                dir.to_string_lossy()
+            );
+        } else {
+            warn!(
+                "Video `{bvid}` fetching done with {} nodes failed: {failed:?}",
+                failed.len()
+            );
+        }
+        Ok(failed)
+    }
+
+    /// 流水线爬取并下载到打包目录 `dir`, 返回剧情树和下载失败的节点
+    ///
+    /// 节点流结束后写入描述文件; 取消或出错时, 若剧情树已爬取完整也会写入,
+    /// 之后可从描述文件继续下载.
+    pub async fn fetch_download<P>(
+        client: &ClientWithMiddleware,
+        bvid: &VideoId,
+        dir: &Path,
+        options: &DownloadOptions,
+        mut progress: P,
+        cancel: &CancelToken,
+    ) -> Result<(Self, Vec<usize>)>
+    where
+        P: FnMut(Progress),
+    {
+        progress(Progress::Metadata);
+        let (mut video, nodes) = cancel
+            .run(Self::fetch_stream(client, bvid))
+            .await
+            .ok_or(fetch::Error::Cancelled)??;
+
+        let result = video
+            .download_stream(client, nodes, dir, options, progress, cancel)
+            .await;
+        if result.is_ok() || video.graph.is_complete() {
+            video.to_file(&dir.join(DATA_FILE))?;
+        }
+        Ok((video, result?))
+    }
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use std::{fs, sync::mpsc};
+
+    use futures::stream;
+    use reqwest_middleware::ClientBuilder;
+    use tempfile::tempdir;
+
+    use crate::{
+        cache::{Cache, Mode},
+        cancel::CancelToken,
+        event::{Event, observe},
+        fetch,
+        model::{Node, Video},
+        package::VIDEO_DIR,
+        utils::sha256,
+        video::{Checksum, Checksums, DownloadOptions},
+    };
+
+    fn video() -> Video {
+        serde_json::from_str(
+            r#"{
+            "id": "BV17x411w7KC", "name": "", "cover": "", "description": "", "author": "",
+            "variables": [], "graph": {"root": 1, "nodes": []}
+        }"#,
+        )
+        .unwrap()
+    }
+
+    fn node(id: usize) -> fetch::Result<Node> {
+        Ok(serde_json::from_str(&format!(
+            r#"{{"id": {id}, "name": "N{id}", "type": "leaf"}}"#
+        ))
+        .unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_download_stream() {
+        let client = ClientBuilder::new(reqwest::Client::new()).build();
+        let options = DownloadOptions {
+            assets: false,
+            ..Default::default()
+        };
+
+        // 已完整下载的节点不发出请求
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(VIDEO_DIR);
+        fs::create_dir_all(&path).unwrap();
+        let mut checksums = Checksums::default();
+        for id in [1, 2] {
+            let content = format!("node {id} content");
+            fs::write(path.join(format!("{id}.mp4")), &content).unwrap();
+            let (size, sha256) = sha256(&mut content.as_bytes()).unwrap();
+            checksums.0.insert(id, Checksum { size, sha256 });
+        }
+        checksums.save(&path).unwrap();
+
+        let mut video = video();
+        let (sender, receiver) = mpsc::channel();
+        let nodes = stream::iter([node(1), node(2)]);
+        let cancel = CancelToken::new();
+        let failed = observe(
+            sender,
+            video.download_stream(&client, nodes, dir.path(), &options, |_| (), &cancel),
+        )
+        .await
+        .unwrap();
+
+        assert!(failed.is_empty());
+        assert_eq!(video.graph.nodes.len(), 2);
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            [Event::NodeSkipped { id: 1 }, Event::NodeSkipped { id: 2 }]
+        );
+
+        // 取消后不再处理节点
+        cancel.cancel();
+        let mut video = self::video();
+        let result = video
+            .download_stream(
+                &client,
+                stream::iter([node(1)]),
+                dir.path(),
+                &options,
+                |_| (),
+                &cancel,
+            )
+            .await;
+        assert!(result.unwrap_err().is_cancelled());
+        assert!(video.graph.nodes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_download_failed() {
+        // 离线且无缓存, 所有下载请求都失败
+        let cache = tempdir().unwrap();
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(Cache::new(cache.path()).with_mode(Mode::Offline))
+            .build();
+        let options = DownloadOptions {
+            assets: false,
+            ..Default::default()
+        };
+        let dir = tempdir().unwrap();
+
+        // 节点 3 由节点 1 的选项发现, 获取前即加入下载队列
+        let root: Node = serde_json::from_str(
+            r#"{"id": 1, "name": "N1", "type": "jump", "choices": [
+                {"id": 11, "name": "C1", "target": 3, "conditions": [], "changes": []}
+            ]}"#,
+        )
+        .unwrap();
+        let mut video = video();
+        let failed = video
+            .download_stream(
+                &client,
+                stream::iter([Ok(root), node(3)]),
+                dir.path(),
+                &options,
+                |_| (),
+                &CancelToken::new(),
+            )
+            .await
+            .unwrap();
+
+        // 下载失败不影响爬取
+        assert_eq!(failed, [1, 3]);
+        assert_eq!(video.graph.nodes.len(), 2);
+        assert!(video.graph.is_complete());
+
+        video.graph.nodes.pop();
+        assert!(!video.graph.is_complete());
+    }
+}
@@ -0,0 +1,337 @@
+//! 离线播放器导出
+//!
+//! 生成单文件 HTML5 播放器, 放在下载目录下即可离线游玩:
+//! 从根节点开始播放, 结束时展示可用选项 (按 `duration` 倒计时, 超时选择默认项),
+//! 执行条件判定和隐藏值修改, 并显示 `show: true` 的变量.
+//! 隐藏选项有坐标时显示为视频上的热区, 否则不显示.
+//!
+//! 页面中的播放规则 (`player/runtime.js`) 是 [`Runtime`](crate::runtime::Runtime) 的 JS 镜像,
+//! 修改规则时须两侧同步, 由 `test_runtime_mirror` 对照.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+
+use crate::{
+    document::TOOL,
+    event::{Event, emit, warning},
+    model::Video,
+    package::{DATA_FILE, video_path},
+};
+
+//////// template ////////
+
+/// 播放器文件名
+pub const PLAYER_FILE: &str = "index.html";
+
+const TEMPLATE: &str = include_str!("player/index.html");
+
+const RUNTIME: &str = include_str!("player/runtime.js");
+
+/// 转义 HTML 文本 (含 `{`, 防止与模板占位符混淆)
+fn escape_html(s: &str) -> String {
+    s.chars()
+        .fold(String::with_capacity(s.len()), |mut out, c| {
+            match c {
+                '&' => out.push_str("&amp;"),
+                '<' => out.push_str("&lt;"),
+                '>' => out.push_str("&gt;"),
+                '"' => out.push_str("&quot;"),
+                '\'' => out.push_str("&#39;"),
+                '{' => out.push_str("&#123;"),
+                c => out.push(c),
+            }
+            out
+        })
+}
+
+/// 转义嵌入 `<script>` 的 JSON
+///
+/// 这些字符在 JSON 中只会出现在字符串内, 换成 `\uXXXX` 后 `JSON.parse` 结果不变.
+fn escape_script(s: &str) -> String {
+    s.chars()
+        .fold(String::with_capacity(s.len()), |mut out, c| {
+            match c {
+                '<' => out.push_str("\\u003c"),
+                '>' => out.push_str("\\u003e"),
+                '&' => out.push_str("\\u0026"),
+                c => out.push(c),
+            }
+            out
+        })
+}
+
+//////// service ////////
+
+impl Video {
+    /// 生成离线播放器页面
+    ///
+    /// 页面按下载目录布局引用节点视频 (`video/{id}.mp4`).
+    pub fn player(&self) -> crate::Result<String> {
+        let data = escape_script(&serde_json::to_string(self)?);
+        Ok(TEMPLATE
+            .replace("{{RUNTIME}}", RUNTIME)
+            .replace("{{TOOL}}", TOOL)
+            .replace("{{TITLE}}", &escape_html(&self.name))
+            .replace("{{DATA}}", &data))
+    }
+
+    /// 在下载目录 `dir` 中生成离线播放器, 返回页面路径
+    ///
+    /// # Notes
+    ///
+    /// - 缺少的节点视频只会记录警告, 播放到该节点时页面无法加载视频
+    pub fn export_player(&self, dir: &Path) -> crate::Result<PathBuf> {
+        let missing = self
+            .graph
+            .nodes
+            .iter()
+            .filter(|n| !dir.join(video_path(n.id)).is_file())
+            .count();
+        if missing > 0 {
+            warn!(
+                "{missing} node videos of `{}` are missing in `{}`",
+                self.id,
+                dir.to_string_lossy()
+            );
+            warning(format!(
+                "缺少 {missing} 个节点视频, 播放到这些节点时无法加载"
+            ));
+        }
+        if !dir.join(DATA_FILE).is_file() {
+            warn!("Exporting player without `{DATA_FILE}` alongside");
+        }
+
+        let path = dir.join(PLAYER_FILE);
+        let player = self.player()?;
+        fs::write(&path, &player)?;
+        emit(Event::FileWritten {
+            path: path.clone(),
+            size: player.len() as u64,
+        });
+
+        info!(
+            "Player of video `{}` exported to `{}`",
+            self.id,
+            path.to_string_lossy()
+        );
+        Ok(path)
+    }
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::Write,
+        process::{Command, Stdio},
+    };
+
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::{RUNTIME, escape_html, escape_script};
+
+    use crate::{
+        model::Video,
+        runtime::{GameState, Runtime},
+    };
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape_html(r#"<a href="x">{{DATA}}&'"#),
+            "&lt;a href=&quot;x&quot;&gt;&#123;&#123;DATA}}&amp;&#39;"
+        );
+        assert_eq!(
+            escape_script(r#"{"name":"</script><!--&"}"#),
+            r#"{"name":"\u003c/script\u003e\u003c!--\u0026"}"#
+        );
+    }
+
+    #[test]
+    fn test_escape_roundtrip() {
+        let value = json!({"name": "</script><!-- -->&amp;", "nested": ["<\\/", "a>b"]});
+        let escaped = escape_script(&value.to_string());
+
+        assert!(!escaped.contains('<') && !escaped.contains('>'));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&escaped).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn test_player() {
+        let video: Video = serde_json::from_value(json!({
+            "id": "BV17x411w7KC", "name": "</title>{{DATA}}", "cover": "", "description": "",
+            "author": "", "variables": [],
+            "graph": {"root": 1, "nodes": [{"id": 1, "name": "</script>", "type": "leaf"}]}
+        }))
+        .unwrap();
+        let html = video.player().unwrap();
+
+        assert!(html.contains("<title>&lt;/title&gt;&#123;&#123;DATA}}</title>"));
+        assert!(html.contains(r#""name":"\u003c/script\u003e""#));
+        assert!(!html.contains("{{TOOL}}") && !html.contains("{{TITLE}}"));
+        assert!(!html.contains("{{RUNTIME}}") && html.contains("function available("));
+        assert_eq!(html.matches("{{DATA}}").count(), 1); // 只出现在数据中
+    }
+
+    /// 某一状态下的判定结果
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Outcome {
+        available: Vec<usize>,
+        jump: Option<usize>,
+        changed: Vec<Vec<f64>>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Mirror {
+        init: Vec<f64>,
+        cases: Vec<Outcome>,
+    }
+
+    /// 在同一组状态上对照 Runtime 与 runtime.js 的判定结果, 没有 node 时跳过
+    #[test]
+    fn test_runtime_mirror() {
+        let video: Video = serde_json::from_value(json!({
+            "id": "BV17x411w7KC", "name": "", "cover": "", "description": "", "author": "",
+            "variables": [
+                {"id": "$a", "name": "A", "type": "normal", "default": 1.5, "show": true},
+                {"id": "$b", "name": "B", "type": "normal", "default": -2, "show": false},
+                {"id": "$r", "name": "R", "type": "random"}
+            ],
+            "graph": {"root": 1, "nodes": [
+                {"id": 1, "name": "N1", "type": "choice", "duration": null, "default": null, "choices": [
+                    {"id": 11, "name": "C1", "target": 2, "hidden": true,
+                     "conditions": [{"type": "less_equal", "id": "$a", "value": 1.9}],
+                     "changes": [{"type": "add", "id": "$a", "value": -0.5}]},
+                    {"id": 12, "name": "C2", "target": 2,
+                     "conditions": [{"type": "not_equal", "id": "$b", "value": -2.7}],
+                     "changes": [{"type": "set", "id": "$b", "value": 3}]},
+                    {"id": 13, "name": "C3", "target": 3, "conditions": [
+                        {"type": "greater_equal", "id": "$a", "value": 2},
+                        {"type": "less", "id": "$b", "value": 0}
+                    ], "changes": [{"type": "add", "id": "$b", "value": 1.25}]}
+                ]},
+                {"id": 2, "name": "N2", "type": "jump", "choices": [
+                    {"id": 21, "name": "J1", "target": 3, "changes": [],
+                     "conditions": [{"type": "greater", "id": "$r", "value": 50}]},
+                    {"id": 22, "name": "J2", "target": 3, "changes": [],
+                     "conditions": [{"type": "equal", "id": "$a", "value": 1}]},
+                    {"id": 23, "name": "J3", "target": 3, "conditions": [], "changes": []}
+                ]},
+                {"id": 3, "name": "N3", "type": "leaf"}
+            ]}
+        }))
+        .unwrap();
+        let runtime = Runtime::new(&video).unwrap();
+
+        let states: Vec<GameState> = [
+            [1.5, -2., 0.],
+            [2.9, -2.5, 51.],
+            [1., 0., 50.],
+            [-0.5, 4., 100.],
+        ]
+        .into_iter()
+        .flat_map(|values| {
+            video.graph.nodes.iter().map(move |n| GameState {
+                node: n.id,
+                values: values.to_vec(),
+                random: Some(0), // 随机值已知
+            })
+        })
+        .collect();
+
+        // Rust 侧: 可用选项, 自动跳转结果, 各可用选项修改后的取值
+        let expected: Vec<Outcome> = states
+            .iter()
+            .map(|state| {
+                let node = runtime.current(state).unwrap();
+                let available: Vec<_> = node
+                    .config
+                    .choices()
+                    .iter()
+                    .filter(|c| runtime.available(state, c).unwrap())
+                    .collect();
+                let changed: Vec<Vec<f64>> = available
+                    .iter()
+                    .map(|c| {
+                        let mut next = state.clone();
+                        for change in &c.changes {
+                            runtime.change(&mut next, change).unwrap();
+                        }
+                        next.values
+                    })
+                    .collect();
+                Outcome {
+                    available: available.iter().map(|c| c.id).collect(),
+                    jump: runtime.jump(state, node).unwrap().map(|c| c.id),
+                    changed,
+                }
+            })
+            .collect();
+
+        // JS 侧: 同样的状态, 变量按声明顺序转换为页面中的 id -> 取值
+        let script = format!(
+            r#"{RUNTIME}
+const video = {video};
+const cases = {cases};
+const ids = video.variables.map(v => v.id);
+const nodes = new Map(video.graph.nodes.map(n => [n.id, n]));
+const toValues = list => Object.fromEntries(ids.map((id, k) => [id, list[k]]));
+console.log(JSON.stringify({{
+  init: ids.map(id => initVariables(video.variables)[id]),
+  cases: cases.map(c => {{
+    const node = nodes.get(c.node);
+    const choices = available(toValues(c.values), node);
+    const changed = choices.map(choice => {{
+      const values = toValues(c.values);
+      choice.changes.forEach(change => apply(values, change));
+      return ids.map(id => values[id]);
+    }});
+    const next = jump(toValues(c.values), node);
+    return {{ available: choices.map(c => c.id), jump: next ? next.id : null, changed }};
+  }}),
+}}));
+"#,
+            video = serde_json::to_string(&video).unwrap(),
+            cases = serde_json::to_string(
+                &states
+                    .iter()
+                    .map(|s| json!({"node": s.node, "values": s.values}))
+                    .collect::<Vec<_>>()
+            )
+            .unwrap(),
+        );
+
+        let Ok(mut node) = Command::new("node")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+        else {
+            eprintln!("`node` not found, skipping runtime mirror test");
+            return;
+        };
+        node.stdin
+            .take()
+            .unwrap()
+            .write_all(script.as_bytes())
+            .unwrap();
+        let output = node.wait_with_output().unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let actual: Mirror = serde_json::from_slice(&output.stdout).unwrap();
+
+        assert_eq!(actual.init, runtime.start(None).unwrap().values);
+        assert_eq!(actual.cases, expected);
+    }
+}
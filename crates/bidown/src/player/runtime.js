@@ -0,0 +1,58 @@
+// 播放规则, 是 runtime.rs 中 Runtime 的 JS 镜像
+//
+// 修改任一侧的规则时须同步修改另一侧, player.rs 中的 test_runtime_mirror 在同一组用例上对照两者.
+// 唯一的差别是随机变量的取值来源: 页面使用 Math.random, Runtime 使用可保存的种子.
+"use strict";
+
+const RANDOM_MAX = 100;
+
+// 普通变量取默认值, 随机变量在进入节点时重新取值
+function initVariables(variables) {
+  const values = {};
+  for (const v of variables) {
+    values[v.id] = v.type === "normal" ? v.default : 0;
+  }
+  return values;
+}
+
+// 随机变量取 [1, RANDOM_MAX] 内的整数
+function rollRandoms(variables, values) {
+  for (const v of variables) {
+    if (v.type === "random") {
+      values[v.id] = Math.floor(Math.random() * RANDOM_MAX) + 1;
+    }
+  }
+}
+
+// 变量和目标值截断为整数后比较
+function check(values, c) {
+  const a = Math.trunc(values[c.id] ?? 0);
+  const b = Math.trunc(c.value);
+  switch (c.type) {
+    case "equal": return a === b;
+    case "not_equal": return a !== b;
+    case "less": return a < b;
+    case "less_equal": return a <= b;
+    case "greater": return a > b;
+    case "greater_equal": return a >= b;
+  }
+  return false;
+}
+
+function apply(values, change) {
+  if (change.type === "add") {
+    values[change.id] = (values[change.id] ?? 0) + change.value;
+  } else {
+    values[change.id] = change.value;
+  }
+}
+
+// 满足全部条件的选项
+function available(values, node) {
+  return (node.choices || []).filter(c => c.conditions.every(cond => check(values, cond)));
+}
+
+// 自动跳转节点按顺序判定, 返回第一个满足条件的选项, 没有时为 null
+function jump(values, node) {
+  return node.type === "jump" ? available(values, node)[0] ?? null : null;
+}
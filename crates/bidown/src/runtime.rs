@@ -0,0 +1,424 @@
+//! 互动视频运行时
+//!
+//! 统一的播放规则: 变量存储, 条件判定, 隐藏值修改, 随机变量取值和节点跳转.
+//! 求解器, 模拟器以及外部工具都应通过 [`Runtime`] 执行剧情图.
+//!
+//! # Notes
+//!
+//! - 条件判定将变量和目标值截断为整数后比较
+//!
+//! - 随机变量在进入节点时重新取 `[1, 100]` 内的整数
+//!
+//! - 自动跳转节点按顺序判定选项, 进入第一个满足条件的选项
+//!
+//! - 未知随机状态 ([`GameState::random`] 为 `None`) 下, 涉及随机变量的判定都视为成功,
+//!   对随机变量的修改被忽略; 自动跳转的可能去向见 [`Runtime::jump_candidates`]
+
+use std::collections::HashMap;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    model::{
+        Change, ChangeKind, Choice, Condition, ConditionKind, Node, NodeConfig, Variable,
+        VariableConfig, Video,
+    },
+    utils::try_all,
+};
+
+//////// random ////////
+
+/// 随机变量的取值上限
+pub const RANDOM_MAX: u64 = 100;
+
+/// SplitMix64 随机数生成器, 状态随游玩状态保存
+fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+//////// state ////////
+
+/// 游玩状态
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameState {
+    /// 当前节点
+    pub node: usize,
+    /// 变量取值, 按声明顺序排列
+    pub values: Vec<f64>,
+    /// 随机数生成器状态, `None` 表示随机变量取值未知
+    pub random: Option<u64>,
+}
+
+//////// runtime ////////
+
+/// 互动视频运行时
+#[derive(Debug, Clone)]
+pub struct Runtime<'a> {
+    video: &'a Video,
+    nodes: HashMap<usize, &'a Node>,
+    variables: HashMap<&'a str, usize>,
+}
+
+impl<'a> Runtime<'a> {
+    pub fn new(video: &'a Video) -> Result<Self> {
+        debug!("Building runtime of video `{}`", video.id);
+
+        let mut variables = HashMap::new();
+        for (k, Variable { id, .. }) in video.variables.iter().enumerate() {
+            if variables.insert(id.as_str(), k).is_some() {
+                return Err(Error::RepeatVariable(id.clone()));
+            }
+        }
+
+        Ok(Self {
+            video,
+            nodes: video.graph.nodes_map(),
+            variables,
+        })
+    }
+
+    pub fn video(&self) -> &'a Video {
+        self.video
+    }
+
+    pub fn node(&self, id: usize) -> Result<&'a Node> {
+        self.nodes.get(&id).copied().ok_or(Error::NodeNotFound(id))
+    }
+
+    /// 从根节点开始的状态
+    ///
+    /// `seed` 为随机数种子, `None` 时随机变量取值未知.
+    pub fn start(&self, seed: Option<u64>) -> Result<GameState> {
+        let values = self
+            .video
+            .variables
+            .iter()
+            .map(|v| match v.config {
+                VariableConfig::Normal { default, .. } => default,
+                VariableConfig::Random => 0.,
+            })
+            .collect();
+
+        let mut state = GameState {
+            node: self.video.graph.root,
+            values,
+            random: seed,
+        };
+        self.enter(&mut state, self.video.graph.root)?;
+        Ok(state)
+    }
+
+    /// 检查外部状态 (如存档) 是否属于此剧情图
+    pub fn validate(&self, state: &GameState) -> Result<()> {
+        self.node(state.node)?;
+        if state.values.len() != self.video.variables.len() {
+            return Err(Error::StateMismatch(state.values.len()));
+        }
+        Ok(())
+    }
+
+    /// 状态所在节点
+    pub fn current(&self, state: &GameState) -> Result<&'a Node> {
+        self.node(state.node)
+    }
+
+    /// 变量及其取值
+    pub fn variables<'s>(
+        &self,
+        state: &'s GameState,
+    ) -> impl Iterator<Item = (&'a Variable, f64)> + 's
+    where
+        'a: 's,
+    {
+        self.video
+            .variables
+            .iter()
+            .zip(state.values.iter().copied())
+    }
+
+    fn index(&self, id: &str) -> Result<usize> {
+        self.variables
+            .get(id)
+            .copied()
+            .ok_or_else(|| Error::VariableNotFound(id.to_string()))
+    }
+
+    fn is_unknown(&self, state: &GameState, k: usize) -> bool {
+        state.random.is_none() && matches!(self.video.variables[k].config, VariableConfig::Random)
+    }
+
+    /// 读取变量取值
+    pub fn value(&self, state: &GameState, id: &str) -> Result<f64> {
+        Ok(state.values[self.index(id)?])
+    }
+
+    /// 检查隐藏值是否符合约束
+    pub fn check(&self, state: &GameState, condition: &Condition) -> Result<bool> {
+        let Condition { kind, id, value } = condition;
+        let k = self.index(id)?;
+        if self.is_unknown(state, k) {
+            return Ok(true);
+        }
+
+        // 作为 isize 比较
+        let variable = state.values[k] as isize;
+        let value = *value as isize;
+
+        Ok(match kind {
+            ConditionKind::Equal => variable == value,
+            ConditionKind::NotEqual => variable != value,
+            ConditionKind::Less => variable < value,
+            ConditionKind::LessEqual => variable <= value,
+            ConditionKind::Greater => variable > value,
+            ConditionKind::GreaterEqual => variable >= value,
+        })
+    }
+
+    /// 检查选项的全部条件
+    pub fn available(&self, state: &GameState, choice: &Choice) -> Result<bool> {
+        try_all(choice.conditions.iter().map(|c| self.check(state, c)))
+    }
+
+    /// 自动跳转节点按顺序判定, 返回第一个满足条件的选项, 其他节点返回 `None`
+    pub fn jump(&self, state: &GameState, node: &'a Node) -> Result<Option<&'a Choice>> {
+        let NodeConfig::Jump { choices } = &node.config else {
+            return Ok(None);
+        };
+        for choice in choices {
+            if self.available(state, choice)? {
+                return Ok(Some(choice));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 自动跳转节点可能进入的选项, 其他节点返回空
+    ///
+    /// 随机值已知时与 [`Runtime::jump`] 相同; 未知时按顺序判定,
+    /// 依赖未知随机变量的选项都可能进入, 直到第一个一定满足条件的选项.
+    pub fn jump_candidates(&self, state: &GameState, node: &'a Node) -> Result<Vec<&'a Choice>> {
+        let NodeConfig::Jump { choices } = &node.config else {
+            return Ok(Vec::new());
+        };
+
+        let mut candidates = Vec::new();
+        for choice in choices {
+            // 未知随机变量的判定视为成功, 其余条件照常判定 (变量均已检查存在)
+            if !self.available(state, choice)? {
+                continue;
+            }
+            candidates.push(choice);
+
+            let uncertain = choice
+                .conditions
+                .iter()
+                .any(|c| self.index(&c.id).is_ok_and(|k| self.is_unknown(state, k)));
+            if !uncertain {
+                break;
+            }
+        }
+        Ok(candidates)
+    }
+
+    /// 修改隐藏值
+    pub fn change(&self, state: &mut GameState, change: &Change) -> Result<()> {
+        let Change { kind, id, value } = change;
+        let k = self.index(id)?;
+        if self.is_unknown(state, k) {
+            return Ok(());
+        }
+
+        let variable = &mut state.values[k];
+        match kind {
+            ChangeKind::Add => *variable += value,
+            ChangeKind::Set => *variable = *value,
+        }
+        Ok(())
+    }
+
+    /// 进入节点, 重新取随机变量
+    fn enter(&self, state: &mut GameState, id: usize) -> Result<()> {
+        self.node(id)?;
+        state.node = id;
+
+        if let Some(random) = &mut state.random {
+            for (v, value) in self.video.variables.iter().zip(&mut state.values) {
+                if matches!(v.config, VariableConfig::Random) {
+                    *value = (next_random(random) % RANDOM_MAX + 1) as f64;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 经选项跳转, 返回新状态
+    ///
+    /// 不检查选项条件和选项是否属于当前节点, 由调用方负责.
+    pub fn transition(&self, state: &GameState, choice: &Choice) -> Result<GameState> {
+        let mut state = state.clone();
+        for change in &choice.changes {
+            self.change(&mut state, change)?;
+        }
+        self.enter(&mut state, choice.target)?;
+        Ok(state)
+    }
+}
+
+//////// service ////////
+
+/// 运行时的返回类型
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// 运行时的错误类型
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("节点 {0} 不存在")]
+    NodeNotFound(usize),
+
+    #[error("变量 `{0}` 不存在")]
+    VariableNotFound(String),
+
+    #[error("变量 `{0}` 重复声明")]
+    RepeatVariable(String),
+
+    #[error("状态含 {0} 个变量, 与剧情图不符")]
+    StateMismatch(usize),
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use super::{Error, Runtime};
+
+    use crate::model::Video;
+
+    const VIDEO: &str = r#"{
+        "id": "BV17x411w7KC", "name": "", "cover": "", "description": "", "author": "",
+        "variables": [
+            {"id": "$v1", "name": "V1", "type": "normal", "default": 0.6, "show": true},
+            {"id": "$r", "name": "R", "type": "random"}
+        ],
+        "graph": {"root": 1, "nodes": [
+            {"id": 1, "name": "N1", "type": "choice", "duration": 5000, "default": null, "choices": [
+                {"id": 11, "name": "C1", "target": 2, "changes": [],
+                 "conditions": [{"type": "equal", "id": "$v1", "value": 0}]},
+                {"id": 12, "name": "C2", "target": 2, "changes": [],
+                 "conditions": [{"type": "greater", "id": "$r", "value": 100}]},
+                {"id": 13, "name": "C3", "target": 3, "conditions": [],
+                 "changes": [{"type": "add", "id": "$v1", "value": 1}]}
+            ]},
+            {"id": 2, "name": "N2", "type": "leaf"}
+        ]}
+    }"#;
+
+    #[test]
+    fn test_runtime() {
+        let video: Video = serde_json::from_str(VIDEO).unwrap();
+        let runtime = Runtime::new(&video).unwrap();
+        let choices = video.graph.nodes[0].config.choices();
+
+        // 截断为整数比较, 随机值取值范围 [1, 100]
+        let state = runtime.start(Some(1)).unwrap();
+        assert!(runtime.available(&state, &choices[0]).unwrap());
+        assert!(!runtime.available(&state, &choices[1]).unwrap());
+        // 随机值未知时判定成功
+        let state = runtime.start(None).unwrap();
+        assert!(runtime.available(&state, &choices[1]).unwrap());
+
+        let next = runtime.transition(&state, &choices[0]).unwrap();
+        assert_eq!(runtime.current(&next).unwrap().id, 2);
+        assert!(matches!(
+            runtime.transition(&state, &choices[2]),
+            Err(Error::NodeNotFound(3))
+        ));
+    }
+
+    #[test]
+    fn test_jump() {
+        let video: Video = serde_json::from_str(
+            r#"{
+            "id": "BV17x411w7KC", "name": "", "cover": "", "description": "", "author": "",
+            "variables": [
+                {"id": "$v1", "name": "V1", "type": "normal", "default": 1, "show": false}
+            ],
+            "graph": {"root": 1, "nodes": [
+                {"id": 1, "name": "N1", "type": "jump", "choices": [
+                    {"id": 11, "name": "", "target": 2, "changes": [],
+                     "conditions": [{"type": "greater", "id": "$v1", "value": 1}]},
+                    {"id": 12, "name": "", "target": 2, "conditions": [], "changes": []},
+                    {"id": 13, "name": "", "target": 2, "conditions": [], "changes": []}
+                ]},
+                {"id": 2, "name": "N2", "type": "leaf"}
+            ]}
+        }"#,
+        )
+        .unwrap();
+        let runtime = Runtime::new(&video).unwrap();
+        let state = runtime.start(Some(0)).unwrap();
+
+        // 按顺序取第一个满足条件的选项
+        let node = runtime.current(&state).unwrap();
+        assert_eq!(runtime.jump(&state, node).unwrap().map(|c| c.id), Some(12));
+        let leaf = runtime.node(2).unwrap();
+        assert!(runtime.jump(&state, leaf).unwrap().is_none());
+
+        let solution = video.solve(8, 8, |_| true, true).unwrap();
+        let leaf = solution.iter_leaf().next().unwrap();
+        assert_eq!(leaf.choice().map(|c| c.id), Some(12));
+    }
+
+    #[test]
+    fn test_jump_random() {
+        let video: Video = serde_json::from_str(
+            r#"{
+            "id": "BV17x411w7KC", "name": "", "cover": "", "description": "", "author": "",
+            "variables": [
+                {"id": "$v1", "name": "V1", "type": "normal", "default": 0, "show": false},
+                {"id": "$r", "name": "R", "type": "random"}
+            ],
+            "graph": {"root": 1, "nodes": [
+                {"id": 1, "name": "N1", "type": "jump", "choices": [
+                    {"id": 11, "name": "", "target": 2, "changes": [],
+                     "conditions": [{"type": "greater", "id": "$v1", "value": 0}]},
+                    {"id": 12, "name": "", "target": 3, "changes": [],
+                     "conditions": [{"type": "greater", "id": "$r", "value": 50}]},
+                    {"id": 13, "name": "", "target": 4, "conditions": [], "changes": []},
+                    {"id": 14, "name": "", "target": 5, "conditions": [], "changes": []}
+                ]},
+                {"id": 2, "name": "N2", "type": "leaf"},
+                {"id": 3, "name": "N3", "type": "leaf"},
+                {"id": 4, "name": "N4", "type": "leaf"},
+                {"id": 5, "name": "N5", "type": "leaf"}
+            ]}
+        }"#,
+        )
+        .unwrap();
+        let runtime = Runtime::new(&video).unwrap();
+        let state = runtime.start(None).unwrap();
+        let node = runtime.current(&state).unwrap();
+
+        // 依赖未知随机值的选项和其后第一个一定满足条件的选项都可能进入
+        let candidates = runtime.jump_candidates(&state, node).unwrap();
+        assert_eq!(
+            candidates.iter().map(|c| c.id).collect::<Vec<_>>(),
+            [12, 13]
+        );
+
+        let leaves = |variable| {
+            let solution = video.solve(8, 8, |_| true, variable).unwrap();
+            let mut leaves: Vec<_> = solution.iter_leaf().map(|s| s.node().id).collect();
+            leaves.sort();
+            leaves
+        };
+        assert_eq!(leaves(true), [3, 4]);
+        // 不判定隐藏值时经过全部选项
+        assert_eq!(leaves(false), [2, 3, 4, 5]);
+    }
+}
@@ -0,0 +1,332 @@
+//! 互动视频游玩模拟
+//!
+//! 在下载之前按剧情图逐步游玩: 维护当前节点和变量, 列出带条件判定结果的选项,
+//! 执行选项的隐藏值修改, 并支持撤销和存档. 播放规则见 [`crate::runtime`].
+//!
+//! 随机数由种子决定, 相同种子和相同选择得到相同的游玩过程.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    id::VideoId,
+    model::{Choice, Condition, Node, NodeConfig, Variable, Video},
+    runtime::{self, GameState, Runtime},
+};
+
+//////// state ////////
+
+/// 存档
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Save {
+    /// 存档对应的视频
+    pub id: VideoId,
+    pub state: GameState,
+    /// 此前经过的状态和选项 (正序)
+    pub history: Vec<(GameState, usize)>,
+}
+
+impl Save {
+    pub fn from_file(path: &Path) -> crate::Result<Self> {
+        debug!("Loading save at `{}`", path.to_string_lossy());
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn to_file(&self, path: &Path) -> crate::Result<()> {
+        debug!("Writing save to `{}`", path.to_string_lossy());
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+}
+
+/// 当前节点的选项及其判定结果
+#[derive(Debug, Clone)]
+pub struct Branch<'a> {
+    pub choice: &'a Choice,
+    /// 各条件的判定结果
+    pub conditions: Vec<(&'a Condition, bool)>,
+    /// 自动跳转节点中被更早的选项抢先
+    pub preempted: bool,
+}
+
+impl Branch<'_> {
+    /// 是否可以选择
+    pub fn available(&self) -> bool {
+        !self.preempted && self.conditions.iter().all(|(_, ok)| *ok)
+    }
+}
+
+//////// simulator ////////
+
+/// 互动视频游玩模拟器
+#[derive(Debug, Clone)]
+pub struct Simulator<'a> {
+    runtime: Runtime<'a>,
+    state: GameState,
+    history: Vec<(GameState, usize)>,
+}
+
+impl<'a> Simulator<'a> {
+    /// 以随机数种子 `seed` 从根节点开始游玩
+    pub fn new(video: &'a Video, seed: u64) -> Result<Self> {
+        let runtime = Runtime::new(video)?;
+        let state = runtime.start(Some(seed))?;
+
+        info!("Start simulating video `{}` with seed {seed}", video.id);
+        Ok(Self {
+            runtime,
+            state,
+            history: Vec::new(),
+        })
+    }
+
+    /// 从存档恢复
+    pub fn load(video: &'a Video, save: Save) -> Result<Self> {
+        if save.id != video.id {
+            return Err(Error::VideoMismatch(save.id));
+        }
+
+        let runtime = Runtime::new(video)?;
+        let Save { state, history, .. } = save;
+        for state in history.iter().map(|(s, _)| s).chain([&state]) {
+            runtime.validate(state)?;
+        }
+
+        info!(
+            "Simulation of video `{}` loaded at node `{}`",
+            video.id, state.node
+        );
+        Ok(Self {
+            runtime,
+            state,
+            history,
+        })
+    }
+
+    /// 生成存档
+    pub fn save(&self) -> Save {
+        Save {
+            id: self.runtime.video().id.clone(),
+            state: self.state.clone(),
+            history: self.history.clone(),
+        }
+    }
+
+    pub fn video(&self) -> &'a Video {
+        self.runtime.video()
+    }
+
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    /// 当前节点
+    pub fn node(&self) -> &'a Node {
+        self.runtime
+            .current(&self.state)
+            .expect("state validated by runtime")
+    }
+
+    /// 变量及其取值
+    pub fn variables(&self) -> impl Iterator<Item = (&'a Variable, f64)> + '_ {
+        self.runtime.variables(&self.state)
+    }
+
+    /// 读取变量取值
+    pub fn value(&self, id: &str) -> Result<f64> {
+        Ok(self.runtime.value(&self.state, id)?)
+    }
+
+    /// 经过的选项 (正序)
+    pub fn path(&self) -> impl Iterator<Item = usize> + '_ {
+        self.history.iter().map(|(_, choice)| *choice)
+    }
+
+    /// 是否抵达叶子节点 (可能是结局)
+    pub fn is_end(&self) -> bool {
+        self.node().is_leaf()
+    }
+
+    /// 列出当前节点的选项并判定条件
+    ///
+    /// 自动跳转节点只有被选中的选项可用.
+    pub fn branches(&self) -> Result<Vec<Branch<'a>>> {
+        let node = self.node();
+        let jump = match node.config {
+            NodeConfig::Jump { .. } => Some(self.runtime.jump(&self.state, node)?.map(|c| c.id)),
+            _ => None,
+        };
+
+        node.config
+            .choices()
+            .iter()
+            .map(|choice| {
+                let conditions = choice
+                    .conditions
+                    .iter()
+                    .map(|c| Ok((c, self.runtime.check(&self.state, c)?)))
+                    .collect::<Result<_>>()?;
+                let preempted = jump.is_some_and(|j| j != Some(choice.id));
+                Ok(Branch {
+                    choice,
+                    conditions,
+                    preempted,
+                })
+            })
+            .collect()
+    }
+
+    /// 当前节点为自动跳转时完成跳转, 返回是否跳转
+    pub fn advance(&mut self) -> Result<bool> {
+        match self.runtime.jump(&self.state, self.node())? {
+            Some(choice) => self.choose(choice.id).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// 选择选项 `id`, 返回抵达的节点
+    pub fn choose(&mut self, id: usize) -> Result<&'a Node> {
+        let branch = self
+            .branches()?
+            .into_iter()
+            .find(|o| o.choice.id == id)
+            .ok_or(Error::ChoiceNotFound(id))?;
+        if !branch.available() {
+            return Err(Error::Unavailable(id));
+        }
+
+        let next = self.runtime.transition(&self.state, branch.choice)?;
+        let previous = std::mem::replace(&mut self.state, next);
+        self.history.push((previous, id));
+
+        let node = self.node();
+        debug!("Choice `{id}` chosen, now at node `{}`", node.id);
+        Ok(node)
+    }
+
+    /// 撤销上一次选择, 没有可撤销的选择时返回 false
+    pub fn undo(&mut self) -> bool {
+        match self.history.pop() {
+            Some((state, _)) => {
+                self.state = state;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 撤销全部选择, 回到起点
+    pub fn restart(&mut self) {
+        if let Some((state, _)) = self.history.first() {
+            self.state = state.clone();
+            self.history.clear();
+        }
+    }
+}
+
+//////// service ////////
+
+/// 模拟过程的返回类型
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// 模拟过程的错误类型
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Runtime(#[from] runtime::Error),
+
+    #[error("当前节点没有选项 {0}")]
+    ChoiceNotFound(usize),
+
+    #[error("选项 {0} 的条件不满足")]
+    Unavailable(usize),
+
+    #[error("存档属于视频 `{0}`")]
+    VideoMismatch(VideoId),
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use super::{Error, Simulator};
+
+    use crate::model::Video;
+
+    const VIDEO: &str = r#"{
+        "id": "BV17x411w7KC", "name": "", "cover": "", "description": "", "author": "",
+        "variables": [
+            {"id": "$v1", "name": "V1", "type": "normal", "default": 0, "show": true},
+            {"id": "$r", "name": "R", "type": "random"}
+        ],
+        "graph": {"root": 1, "nodes": [
+            {"id": 1, "name": "N1", "type": "choice", "duration": 5000, "default": null, "choices": [
+                {"id": 11, "name": "C1", "target": 1, "conditions": [],
+                 "changes": [{"type": "add", "id": "$v1", "value": 1}]},
+                {"id": 12, "name": "C2", "target": 2, "changes": [],
+                 "conditions": [{"type": "greater_equal", "id": "$v1", "value": 2}]}
+            ]},
+            {"id": 2, "name": "N2", "type": "leaf"}
+        ]}
+    }"#;
+
+    #[test]
+    fn test_simulate() {
+        let video: Video = serde_json::from_str(VIDEO).unwrap();
+        let mut sim = Simulator::new(&video, 42).unwrap();
+
+        let available = |sim: &Simulator| -> Vec<bool> {
+            sim.branches()
+                .unwrap()
+                .iter()
+                .map(|o| o.available())
+                .collect()
+        };
+        assert_eq!(available(&sim), vec![true, false]);
+        assert!(matches!(sim.choose(12), Err(Error::Unavailable(12))));
+        assert!(matches!(sim.choose(13), Err(Error::ChoiceNotFound(13))));
+
+        sim.choose(11).unwrap();
+        sim.choose(11).unwrap();
+        assert_eq!(sim.value("$v1").unwrap(), 2.);
+        assert_eq!(available(&sim), vec![true, true]);
+
+        assert_eq!(sim.choose(12).unwrap().id, 2);
+        assert!(sim.is_end());
+        assert_eq!(sim.path().collect::<Vec<_>>(), vec![11, 11, 12]);
+
+        assert!(sim.undo());
+        assert_eq!(sim.node().id, 1);
+        sim.restart();
+        assert_eq!(sim.value("$v1").unwrap(), 0.);
+        assert!(!sim.undo());
+    }
+
+    #[test]
+    fn test_seed_and_save() {
+        let video: Video = serde_json::from_str(VIDEO).unwrap();
+        let play = |seed| {
+            let mut sim = Simulator::new(&video, seed).unwrap();
+            (0..3).for_each(|_| _ = sim.choose(11).unwrap());
+            sim
+        };
+
+        let sim = play(7);
+        let random = sim.value("$r").unwrap();
+        assert!((1. ..=100.).contains(&random));
+        assert_eq!(play(7).state(), sim.state());
+
+        let save = serde_json::to_string(&sim.save()).unwrap();
+        let mut loaded = Simulator::load(&video, serde_json::from_str(&save).unwrap()).unwrap();
+        assert_eq!(loaded.state(), sim.state());
+        assert!(loaded.undo());
+        assert_eq!(loaded.value("$v1").unwrap(), 2.);
+    }
+}
@@ -11,11 +11,9 @@ use serde::{Serialize, Serializer, ser::SerializeSeq};
 use thiserror::Error;
 
 use crate::{
-    model::{
-        Change, ChangeKind, Choice, Condition, ConditionKind, Graph, Node, NodeConfig, Variable,
-        VariableConfig, Video,
-    },
-    utils::try_all,
+    Progress,
+    model::{Choice, Graph, Node, NodeConfig, Video},
+    runtime::{self, GameState, Runtime},
 };
 
 //////// model ////////
@@ -154,118 +152,26 @@ impl<'a> Solution<'a> {
     }
 }
 
-//////// context ////////
-
-/// 变量托管
-#[derive(Debug, Clone)]
-struct Variables<'a> {
-    randoms: Rc<HashSet<&'a str>>,
-    normals: HashMap<&'a str, f64>,
-}
-
-impl<'a> Variables<'a> {
-    fn new<I>(vars: I) -> Result<Self>
-    where
-        I: Iterator<Item = &'a Variable>,
-    {
-        debug!("Building variables' execution");
-
-        let mut randoms = HashSet::new();
-        let mut normals = HashMap::new();
-
-        for Variable { id, config, .. } in vars {
-            let id = id.as_str();
-            let existed = match config {
-                VariableConfig::Normal { default, .. } => normals.insert(id, *default).is_some(),
-                VariableConfig::Random if !normals.contains_key(id) => !randoms.insert(id),
-                _ => true,
-            };
-
-            if existed {
-                return Err(Error::RepeatVariable(id.to_string()));
-            }
-        }
-
-        Ok(Self {
-            randoms: Rc::new(randoms),
-            normals,
-        })
-    }
-
-    /// 检查隐藏值是否符合约束
-    ///
-    /// # Notes
-    ///
-    /// - 所有随机值产生的判定都将被视为成功
-    fn check(&self, condition: &Condition) -> Result<bool> {
-        let Condition { kind, id, value } = condition;
-        let id = id.as_str();
-
-        // 随机值直接通过
-        if self.randoms.contains(id) {
-            return Ok(true);
-        }
-
-        // 作为 isize 比较
-        let variable = *self
-            .normals
-            .get(id)
-            .ok_or_else(|| Error::VariableNotFound(id.to_string()))?
-            as isize;
-        let value = *value as isize;
-
-        let relation = match kind {
-            ConditionKind::Equal => variable == value,
-            ConditionKind::NotEqual => variable != value,
-            ConditionKind::Less => variable < value,
-            ConditionKind::LessEqual => variable <= value,
-            ConditionKind::Greater => variable > value,
-            ConditionKind::GreaterEqual => variable >= value,
-        };
-        Ok(relation)
-    }
-
-    fn change(&mut self, change: &Change) -> Result<()> {
-        let Change { kind, id, value } = change;
-        let id = id.as_str();
-
-        // 随机值不管
-        if self.randoms.contains(id) {
-            return Ok(());
-        }
-
-        // 执行更改
-        let variable = self
-            .normals
-            .get_mut(id)
-            .ok_or_else(|| Error::VariableNotFound(id.to_string()))?;
-        match kind {
-            ChangeKind::Add => *variable += value,
-            ChangeKind::Set => *variable = *value,
-        }
-
-        Ok(())
-    }
-}
-
 //////// state ////////
 
 const ZOOM_MAGIC: f64 = 24.99;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct State<'a> {
+struct State {
     node: usize,
-    variables: Vec<(&'a str, isize)>,
+    values: Vec<isize>,
 }
 
-impl<'a> State<'a> {
-    fn new(node: usize, variables: &HashMap<&'a str, f64>) -> Self {
-        let mut variables: Vec<_> = variables
-            .iter()
-            .map(|(&k, &v)| (k, (v * ZOOM_MAGIC) as isize))
-            .collect();
-        variables.sort();
-        Self { node, variables }
+impl From<&GameState> for State {
+    fn from(value: &GameState) -> Self {
+        Self {
+            node: value.node,
+            values: value
+                .values
+                .iter()
+                .map(|v| (v * ZOOM_MAGIC) as isize)
+                .collect(),
+        }
     }
 }
 
@@ -277,14 +183,8 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// 求解过程的错误类型
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("节点 {0} 不存在")]
-    NodeNotFound(usize),
-
-    #[error("变量 `{0}` 不存在")]
-    VariableNotFound(String),
-
-    #[error("变量 `{0}` 重复声明")]
-    RepeatVariable(String),
+    #[error(transparent)]
+    Runtime(#[from] runtime::Error),
 }
 
 impl Graph {
@@ -322,43 +222,60 @@ impl Video {
     /// # Notes
     ///
     /// - 所有随机值产生的判定都将被视为成功
+    ///
+    /// - 自动跳转节点不是自由选择, 只经过可能进入的选项 (见 [`Runtime::jump_candidates`]);
+    ///   不判定隐藏值时经过全部选项
     pub fn solve<P>(
+        &self,
+        maxd: usize,
+        cutd: usize,
+        pred: P,
+        variable: bool,
+    ) -> Result<Solution<'_>>
+    where
+        P: FnMut(&Choice) -> bool,
+    {
+        self.solve_with_progress(maxd, cutd, pred, variable, |_| ())
+    }
+
+    /// 求解互动视频, 每求得一个节点的路径时报告进度
+    ///
+    /// 参数含义见 [`Video::solve`].
+    pub fn solve_with_progress<P, F>(
         &self,
         maxd: usize,
         cutd: usize,
         mut pred: P,
         variable: bool,
+        mut progress: F,
     ) -> Result<Solution<'_>>
     where
         P: FnMut(&Choice) -> bool,
+        F: FnMut(Progress),
     {
         info!("Start solving graph of video `{}`", self.id);
 
         let total = self.graph.nodes.len();
-        let nodes = self.graph.nodes_map();
-        let get_node = |id| match nodes.get(&id) {
-            Some(&n) => Ok(n),
-            None => Err(Error::NodeNotFound(id)),
-        };
+        let runtime = Runtime::new(self)?;
 
         let mut solution = Vec::with_capacity(total);
         let mut visit = HashSet::new();
         let mut visit_states = HashSet::new();
         let mut queue = VecDeque::new();
 
-        let root = get_node(self.graph.root)?;
-        let variables = Variables::new(self.variables.iter())?;
-        queue.push_back((0, Rc::new(Step::new(root)), variables));
+        // 随机值未知, 相关判定都视为成功
+        let state = runtime.start(None)?;
+        let root = runtime.current(&state)?;
+        queue.push_back((0, Rc::new(Step::new(root)), state));
 
         let mut current_dep = 0; // debug!()
 
         // BFS
-        while let Some((dep, step, variables)) = queue.pop_front() {
+        while let Some((dep, step, state)) = queue.pop_front() {
             let node = step.node();
 
             // 不走重复状态 (实测, 这个剪枝出乎意料的猛! 相当于走路变成了乘火箭)
-            let state = State::new(node.id, &variables.normals);
-            if !visit_states.insert(state) {
+            if !visit_states.insert(State::from(&state)) {
                 continue;
             }
 
@@ -376,6 +293,12 @@ impl Video {
                     "Node `{}` solved, name=`{}`, progress={current}/{total}",
                     node.id, node.name
                 );
+                progress(Progress::Solve {
+                    current,
+                    total,
+                    id: node.id,
+                    name: node.name.clone(),
+                });
 
                 // 找完提前结束
                 if current == total {
@@ -387,22 +310,20 @@ impl Video {
                 continue;
             }
 
-            let choices = match &node.config {
-                NodeConfig::Choice { choices, .. } => choices,
-                _ => continue, // 此后只能推入邻边, 不能放其他逻辑!
+            let choices: Vec<&Choice> = match &node.config {
+                NodeConfig::Choice { choices, .. } => choices.iter().collect(),
+                // 自动跳转只能走可能进入的选项
+                NodeConfig::Jump { choices } => match variable {
+                    true => runtime.jump_candidates(&state, node)?,
+                    false => choices.iter().collect(),
+                },
+                NodeConfig::Leaf => continue, // 此后只能推入邻边, 不能放其他逻辑!
             };
 
             // 走到下一个节点
             for choice in choices {
-                let Choice {
-                    target,
-                    conditions,
-                    changes,
-                    ..
-                } = choice;
-
                 // 过滤掉经过的点
-                if dep >= cutd && visit.contains(target) {
+                if dep >= cutd && visit.contains(&choice.target) {
                     continue;
                 }
 
@@ -412,20 +333,15 @@ impl Video {
                 }
 
                 // 判定隐藏值
-                if variable && !try_all(conditions.iter().map(|c| variables.check(c)))? {
+                if variable && !runtime.available(&state, choice)? {
                     continue;
                 }
 
-                // 修改隐藏值
-                let mut variables = variables.clone();
-                for change in changes {
-                    variables.change(change)?;
-                }
-
-                // 推入邻边
-                let node = get_node(*target)?;
+                // 修改隐藏值, 推入邻边
+                let next = runtime.transition(&state, choice)?;
+                let node = runtime.current(&next)?;
                 let step = Rc::new(Step::new_linked(node, choice, step.clone()));
-                queue.push_back((dep + 1, step, variables));
+                queue.push_back((dep + 1, step, next));
             }
         }
 
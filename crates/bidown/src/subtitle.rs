@@ -0,0 +1,125 @@
+//! 字幕格式转换
+//!
+//! 将 B 站 JSON 字幕 (`subtitle_url` 指向的文件) 转换为 SRT 或 WebVTT.
+
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+//////// model ////////
+
+/// B 站 JSON 字幕
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Subtitle {
+    pub body: Vec<Line>,
+}
+
+/// 字幕行, 时间以秒为单位
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Line {
+    pub from: f64,
+    pub to: f64,
+    pub content: String,
+}
+
+/// 字幕输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    #[default]
+    Srt,
+    WebVtt,
+}
+
+impl Format {
+    /// 文件扩展名
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Srt => "srt",
+            Self::WebVtt => "vtt",
+        }
+    }
+}
+
+//////// convert ////////
+
+/// 格式化时间戳 `hh:mm:ss{sep}mmm`
+fn timestamp(secs: f64, sep: char) -> String {
+    let ms = (secs.max(0.) * 1000.).round() as u64;
+    let (h, m, s, ms) = (ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000);
+    format!("{h:02}:{m:02}:{s:02}{sep}{ms:03}")
+}
+
+/// 去除空行, 空行在两种格式中都表示字幕块结束
+fn lines(content: &str) -> impl Iterator<Item = &str> {
+    content.lines().map(str::trim).filter(|l| !l.is_empty())
+}
+
+impl Subtitle {
+    pub fn to_srt(&self) -> String {
+        let mut out = String::new();
+        for (k, Line { from, to, content }) in self.body.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "{}\n{} --> {}",
+                k + 1,
+                timestamp(*from, ','),
+                timestamp(*to, ',')
+            );
+            for line in lines(content) {
+                let _ = writeln!(out, "{line}");
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn to_vtt(&self) -> String {
+        let mut out = String::from("WEBVTT\n\n");
+        for (k, Line { from, to, content }) in self.body.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "{}\n{} --> {}",
+                k + 1,
+                timestamp(*from, '.'),
+                timestamp(*to, '.')
+            );
+            for line in lines(content) {
+                let line = line.replace('&', "&amp;").replace('<', "&lt;");
+                let _ = writeln!(out, "{line}");
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn convert(&self, format: Format) -> String {
+        match format {
+            Format::Srt => self.to_srt(),
+            Format::WebVtt => self.to_vtt(),
+        }
+    }
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use super::{Subtitle, timestamp};
+
+    const FIXTURE: &str = include_str!("../fixtures/subtitle.json");
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(0., ','), "00:00:00,000");
+        assert_eq!(timestamp(3661.001, '.'), "01:01:01.001");
+        assert_eq!(timestamp(59.9996, ','), "00:01:00,000");
+    }
+
+    #[test]
+    fn test_convert() {
+        let subtitle: Subtitle = serde_json::from_str(FIXTURE).unwrap();
+        assert_eq!(subtitle.to_srt(), include_str!("../fixtures/subtitle.srt"));
+        assert_eq!(subtitle.to_vtt(), include_str!("../fixtures/subtitle.vtt"));
+    }
+}
@@ -1,22 +1,121 @@
-use std::fmt::Debug;
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Debug,
+    hash::{BuildHasher, Hasher},
+    io::{self, Read},
+    time::Duration,
+};
 
+use bytes::Bytes;
+use reqwest::{ResponseBuilderExt, StatusCode, Url, Version, header::HeaderMap};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-/// 工作进度
+/// 工作进度事件, 按阶段区分
 ///
 /// # Notes
 ///
-/// - 以节点作为度量单位
+/// - 节点计数以节点作为度量单位, 字节进度只在下载阶段报告
 ///
-/// - 若 `total` 未知, 请使用最大值.
-#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
-pub struct Progress {
-    // progress
-    pub current: usize,
-    pub total: usize,
-    // metadata
-    pub id: usize,
-    pub name: String,
+/// - 爬取阶段的总节点数未知, 以已获取节点数与待访问边界之和估计
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "phase", rename_all = "snake_case")]
+pub enum Progress {
+    /// 爬取元数据和准备信息
+    Metadata,
+    /// 爬取剧情树, 每获取一个节点报告一次
+    Crawl {
+        current: usize,
+        /// 估计的总节点数, 随爬取推进而修正
+        estimated: usize,
+        id: usize,
+        name: String,
+    },
+    /// 下载节点视频, 传输中按数据块报告, 节点完成时 `current` 增加
+    Download {
+        /// 已完成的节点数
+        current: usize,
+        total: usize,
+        id: usize,
+        name: String,
+        transfer: Transfer,
+    },
+    /// 求解, 每求得一个节点的路径时报告一次
+    Solve {
+        current: usize,
+        total: usize,
+        id: usize,
+        name: String,
+    },
+}
+
+impl Progress {
+    /// 当前阶段的完成比例, 元数据阶段为 `None`
+    pub fn fraction(&self) -> Option<f64> {
+        let ratio = |current: f64, total: usize| (current / total.max(1) as f64).min(1.);
+        match self {
+            Self::Metadata => None,
+            Self::Crawl {
+                current, estimated, ..
+            } => Some(ratio(*current as f64, *estimated)),
+            Self::Download {
+                current,
+                total,
+                transfer,
+                ..
+            } => {
+                // 计入传输中节点的部分进度
+                let partial = match transfer.fraction() {
+                    Some(f) if !transfer.done => f,
+                    _ => 0.,
+                };
+                Some(ratio(*current as f64 + partial, *total))
+            }
+            Self::Solve { current, total, .. } => Some(ratio(*current as f64, *total)),
+        }
+    }
+}
+
+/// 单个文件的传输进度
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Transfer {
+    /// 已下载字节数
+    pub downloaded: u64,
+    /// 总字节数, 服务器未提供 Content-Length 时为 `None`
+    pub total: Option<u64>,
+    /// 平均传输速度 (字节/秒)
+    pub speed: f64,
+    /// 预计剩余时间
+    pub eta: Option<Duration>,
+    /// 传输已完成
+    pub done: bool,
+}
+
+impl Transfer {
+    /// 由开始传输以来的耗时计算速度和剩余时间
+    pub fn new(downloaded: u64, total: Option<u64>, elapsed: Duration) -> Self {
+        let secs = elapsed.as_secs_f64();
+        let speed = if secs > 0. {
+            downloaded as f64 / secs
+        } else {
+            0.
+        };
+        let eta = total
+            .filter(|_| speed > 0.)
+            .map(|t| Duration::from_secs_f64(t.saturating_sub(downloaded) as f64 / speed));
+        Self {
+            downloaded,
+            total,
+            speed,
+            eta,
+            done: false,
+        }
+    }
+
+    pub fn fraction(&self) -> Option<f64> {
+        self.total
+            .map(|t| (self.downloaded as f64 / t.max(1) as f64).min(1.))
+    }
 }
 
 /// 单元素 Vec 解包, 失败时返回长度
@@ -41,6 +140,51 @@ where
     Ok(true)
 }
 
+/// 生成 `[0, max)` 内的随机时长
+pub fn jitter(max: Duration) -> Duration {
+    let max = max.as_nanos() as u64;
+    if max == 0 {
+        return Duration::ZERO;
+    }
+    let random = RandomState::new().build_hasher().finish();
+    Duration::from_nanos(random % max)
+}
+
+#[derive(Debug, Deserialize)]
+struct Code {
+    code: i64,
+}
+
+/// 读取 JSON 响应体中的业务码
+pub fn response_code(body: &[u8]) -> Option<i64> {
+    serde_json::from_slice::<Code>(body).ok().map(|c| c.code)
+}
+
+/// 以缓冲好的响应体重新构造响应
+pub fn rebuild_response(
+    url: Url,
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+) -> reqwest::Response {
+    let mut response = http::Response::builder()
+        .status(status)
+        .version(version)
+        .url(url)
+        .body(body)
+        .expect("response parts are already validated");
+    *response.headers_mut() = headers;
+    response.into()
+}
+
+/// 计算数据流的长度和 SHA-256 (十六进制小写)
+pub fn sha256<R: Read>(reader: &mut R) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(reader, &mut hasher)?;
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
 /// 依据 id 字段添加 PartialEq 实现
 #[macro_export]
 macro_rules! impl_pareq_with_id {
@@ -82,3 +226,34 @@ where
     //     self.data.try_into()
     // }
 }
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Progress, Transfer};
+
+    #[test]
+    fn test_progress_fraction() {
+        let transfer = Transfer::new(25, Some(100), Duration::from_secs(5));
+        assert_eq!(transfer.speed, 5.);
+        assert_eq!(transfer.eta, Some(Duration::from_secs(15)));
+        assert_eq!(transfer.fraction(), Some(0.25));
+
+        // 未知总大小时没有剩余时间
+        let unknown = Transfer::new(25, None, Duration::from_secs(5));
+        assert_eq!(unknown.eta, None);
+
+        let progress = Progress::Download {
+            current: 1,
+            total: 4,
+            id: 2,
+            name: "N2".into(),
+            transfer,
+        };
+        assert_eq!(progress.fraction(), Some(0.3125));
+        assert_eq!(Progress::Metadata.fraction(), None);
+    }
+}
@@ -1,21 +1,32 @@
 //! 视频下载
 
 use std::{
+    collections::{BTreeMap, HashMap},
     fmt::{Display, Formatter},
-    fs,
+    fs::{self, File},
+    io,
     path::Path,
+    time::{Duration, Instant},
 };
 
-use bytes::Bytes;
-use log::{debug, info};
+use bytes::{Bytes, BytesMut};
+use futures::{StreamExt, TryStreamExt, stream};
+use log::{debug, info, warn};
+use reqwest::header::CONTENT_TYPE;
 use reqwest_middleware::ClientWithMiddleware;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tokio::sync::mpsc;
 
 use crate::{
-    Progress,
+    Progress, Transfer,
+    cancel::CancelToken,
+    event::{Event, emit, warning},
+    id::VideoId,
     model::{Node, Video},
-    utils::Response,
+    package::{AVATAR_STEM, Assets, COVER_STEM, VIDEO_DIR, thumbnail_stem},
+    subtitle::{self, Subtitle},
+    utils::{Response, sha256},
 };
 
 //////// download ////////
@@ -28,7 +39,10 @@ use crate::{
 /// | Medium | 720P | 64 |
 /// | Low | 480P | 32 |
 /// | VeryLow | 360P | 16 |
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
 #[repr(u8)]
 pub enum Quality {
     #[default]
@@ -63,19 +77,61 @@ struct Durl {
     url: String,
 }
 
-async fn download_to_bytes(client: &ClientWithMiddleware, url: &str) -> Result<Bytes> {
-    let response = client.get(url).send().await?;
-    let bytes = response.bytes().await?;
+/// 传输进度的最小报告间隔
+const TRANSFER_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 下载完整响应体, 与 Content-Length 不符时视为截断
+///
+/// 传输进度至多每 [`TRANSFER_INTERVAL`] 报告一次, 完成时总会报告.
+async fn download_to_bytes<P>(
+    client: &ClientWithMiddleware,
+    url: &str,
+    mut progress: P,
+) -> Result<Bytes>
+where
+    P: FnMut(Transfer),
+{
+    let start = Instant::now();
+    let mut response = client.get(url).send().await?;
+    let expected = response.content_length();
+
+    let mut buffer = BytesMut::new();
+    let mut reported = start;
+    while let Some(chunk) = response.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+        if reported.elapsed() >= TRANSFER_INTERVAL {
+            reported = Instant::now();
+            progress(Transfer::new(
+                buffer.len() as u64,
+                expected,
+                start.elapsed(),
+            ));
+        }
+    }
+    progress(Transfer {
+        done: true,
+        ..Transfer::new(buffer.len() as u64, expected, start.elapsed())
+    });
+    let bytes = buffer.freeze();
+
+    if let Some(expected) = expected
+        && bytes.len() as u64 != expected
+    {
+        return Err(Error::Truncated {
+            expected,
+            actual: bytes.len() as u64,
+        });
+    }
     Ok(bytes)
 }
 
-/// 获取普通 MP4 视频流
-async fn fetch_video(
+/// 获取普通 MP4 视频流的地址
+async fn stream_url(
     client: &ClientWithMiddleware,
-    bvid: &str,
+    bvid: &VideoId,
     cid: usize,
     quality: Quality,
-) -> Result<Bytes> {
+) -> Result<String> {
     let url = format!(
         "https://api.bilibili.com/x/player/playurl?bvid={bvid}&cid={cid}&qn={quality}&fnval=0&otype=json"
     );
@@ -83,24 +139,382 @@ async fn fetch_video(
     let response = client.get(url.as_str()).send().await?;
     let data = response.json::<Response<VideoData>>().await?.data;
     let url = data.url().ok_or(Error::StreamNotFound(url))?;
-    download_to_bytes(client, url).await
+    emit(Event::StreamSelected {
+        id: cid,
+        quality,
+        url: url.to_string(),
+    });
+    Ok(url.to_string())
+}
+
+/// 获取普通 MP4 视频流
+async fn fetch_video(
+    client: &ClientWithMiddleware,
+    bvid: &VideoId,
+    cid: usize,
+    quality: Quality,
+    progress: impl FnMut(Transfer),
+) -> Result<Bytes> {
+    let url = stream_url(client, bvid, cid, quality).await?;
+    download_to_bytes(client, &url, progress).await
 }
 
-/// 下载一个节点的视频
-pub async fn download(
+/// 写入文件并报告事件
+fn write_file(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let contents = contents.as_ref();
+    fs::write(path, contents)?;
+    emit(Event::FileWritten {
+        path: path.to_path_buf(),
+        size: contents.len() as u64,
+    });
+    Ok(())
+}
+
+/// 下载一个节点的视频, 返回完整性记录
+pub async fn download<P>(
     client: &ClientWithMiddleware,
     path: &Path,
-    bvid: &str,
+    bvid: &VideoId,
     cid: usize,
     quality: Quality,
-) -> Result<()> {
+    progress: P,
+) -> Result<Checksum>
+where
+    P: FnMut(Transfer),
+{
     info!(
         "Downloading video node {cid} to `{}`",
         path.to_string_lossy()
     );
-    let video = fetch_video(client, bvid, cid, quality).await?;
-    fs::write(path, video)?;
-    Ok(())
+    let video = fetch_video(client, bvid, cid, quality, progress).await?;
+    let (size, sha256) = sha256(&mut video.as_ref())?;
+    write_file(path, video)?;
+    Ok(Checksum { size, sha256 })
+}
+
+//////// integrity ////////
+
+/// 完整性记录文件, 位于节点视频目录下
+pub const CHECKSUM_FILE: &str = "checksums.json";
+
+/// 节点视频的完整性记录
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checksum {
+    pub size: u64,
+    /// SHA-256 (十六进制小写)
+    pub sha256: String,
+}
+
+impl Checksum {
+    /// 文件的大小和哈希是否与记录一致
+    ///
+    /// 大小一致时才在阻塞线程中计算哈希.
+    pub async fn matches(&self, file: &Path) -> bool {
+        fs::metadata(file).is_ok_and(|m| m.len() == self.size)
+            && hash_file(file).await.is_ok_and(|c| c == *self)
+    }
+}
+
+/// 节点 id -> 完整性记录
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checksums(pub BTreeMap<usize, Checksum>);
+
+impl Checksums {
+    /// 读取节点视频目录中的记录, 不存在时为空
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read(path.join(CHECKSUM_FILE)) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path.join(CHECKSUM_FILE), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// 校验报告, 均为节点 id
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Verification {
+    /// 文件不存在
+    pub missing: Vec<usize>,
+    /// 文件小于记录的大小
+    pub truncated: Vec<usize>,
+    /// 大小或校验和不符, 或无法读取
+    pub corrupt: Vec<usize>,
+    /// 文件存在但没有记录, 无法离线校验 (见 [`Video::repair`])
+    pub unrecorded: Vec<usize>,
+}
+
+/// 在阻塞线程中计算文件的大小和哈希
+async fn hash_file(file: &Path) -> Result<Checksum> {
+    let file = file.to_path_buf();
+    let (size, sha256) = tokio::task::spawn_blocking(move || sha256(&mut File::open(file)?))
+        .await
+        .map_err(io::Error::other)??;
+    Ok(Checksum { size, sha256 })
+}
+
+/// 断点续传时检查已有的节点视频, 返回可以沿用的完整性记录, `None` 表示需要 (重新) 下载
+///
+/// - 有记录时先比较大小, 一致时再计算哈希
+/// - 没有记录 (如旧版本的下载) 时与服务器的 Content-Length 比较, 一致时补录记录
+pub(crate) async fn check_existing(
+    client: &ClientWithMiddleware,
+    file: &Path,
+    bvid: &VideoId,
+    cid: usize,
+    quality: Quality,
+    recorded: Option<&Checksum>,
+) -> Result<Option<Checksum>> {
+    if let Some(checksum) = recorded {
+        return Ok(checksum.matches(file).await.then(|| checksum.clone()));
+    }
+    let size = match fs::metadata(file) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let url = stream_url(client, bvid, cid, quality).await?;
+    // 只读取响应头, 丢弃响应时中断传输
+    let response = client.get(url).send().await?.error_for_status()?;
+    if response.content_length() != Some(size) {
+        return Ok(None);
+    }
+    hash_file(file).await.map(Some)
+}
+
+impl Verification {
+    /// 是否没有需要重新下载的节点
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.truncated.is_empty() && self.corrupt.is_empty()
+    }
+
+    /// 确定需要重新下载的节点, 不含没有记录的节点
+    pub fn broken(&self) -> impl Iterator<Item = usize> + '_ {
+        self.missing
+            .iter()
+            .chain(&self.truncated)
+            .chain(&self.corrupt)
+            .copied()
+    }
+}
+
+//////// options ////////
+
+/// 已有节点视频的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Overwrite {
+    /// 跳过校验通过的节点, 只下载缺失, 截断或损坏的节点
+    ///
+    /// 没有记录的已有文件与服务器的 Content-Length 一致时补录记录, 否则重新下载.
+    #[default]
+    Resume,
+    /// 重新下载全部节点
+    Replace,
+}
+
+/// 下载选项, 见 [`Video::download_with`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadOptions {
+    pub quality: Quality,
+    pub overwrite: Overwrite,
+    /// 同时下载的节点数, 至少为 1
+    pub concurrency: usize,
+    /// 是否下载封面, 头像和缩略图
+    pub assets: bool,
+    pub extras: Extras,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            quality: Quality::default(),
+            overwrite: Overwrite::default(),
+            concurrency: 1,
+            assets: true,
+            extras: Extras::default(),
+        }
+    }
+}
+
+//////// asset ////////
+
+/// 按 Content-Type 推断图片扩展名, 无法识别时参考 URL
+fn image_extension(content_type: Option<&str>, url: &str) -> &'static str {
+    const KNOWN: [(&str, &str); 5] = [
+        ("image/jpeg", "jpg"),
+        ("image/png", "png"),
+        ("image/webp", "webp"),
+        ("image/gif", "gif"),
+        ("image/avif", "avif"),
+    ];
+
+    let by_type = content_type.and_then(|t| {
+        let t = t.split(';').next().unwrap_or_default().trim();
+        KNOWN.iter().find(|(k, _)| t.eq_ignore_ascii_case(k))
+    });
+    let by_url = || {
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+        let ext = path.rsplit_once('.').map(|(_, e)| e.to_ascii_lowercase())?;
+        let ext = if ext == "jpeg" {
+            "jpg".to_string()
+        } else {
+            ext
+        };
+        KNOWN.iter().find(|(_, e)| *e == ext)
+    };
+
+    by_type.or_else(by_url).map_or("jpg", |(_, e)| e)
+}
+
+/// 下载一张图片到 `dir` 下的 `{stem}.{ext}`, 返回相对路径
+async fn download_image(
+    client: &ClientWithMiddleware,
+    dir: &Path,
+    stem: &str,
+    url: &str,
+) -> Result<String> {
+    debug!("Downloading image `{url}` as `{stem}`");
+    let response = client.get(url).send().await?.error_for_status()?;
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let bytes = response.bytes().await?;
+
+    let path = format!("{stem}.{}", image_extension(content_type.as_deref(), url));
+    let local = path.split('/').fold(dir.to_path_buf(), |p, s| p.join(s));
+    if let Some(parent) = local.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_file(&local, bytes)?;
+    Ok(path)
+}
+
+//////// extra ////////
+
+/// 弹幕格式
+///
+/// | 枚举项 | 接口 | 文件 |
+/// | --- | --- | --- |
+/// | Xml | `x/v1/dm/list.so` | `{id}.danmaku.xml` |
+/// | Protobuf | `x/v2/dm/web/seg.so` (6 分钟一段) | `{id}.danmaku.{n}.pb` |
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Danmaku {
+    #[default]
+    Xml,
+    Protobuf,
+}
+
+/// 弹幕分段数上限 (约 6 小时)
+const DANMAKU_SEGMENT_MAX: usize = 60;
+
+/// 字幕和弹幕下载选项, 为 `None` 的项不下载
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Extras {
+    pub subtitle: Option<subtitle::Format>,
+    pub danmaku: Option<Danmaku>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PlayerData {
+    #[serde(default)]
+    subtitle: SubtitleData,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct SubtitleData {
+    #[serde(default)]
+    subtitles: Vec<Track>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Track {
+    #[serde(rename = "lan")]
+    language: String,
+    #[serde(rename = "subtitle_url")]
+    url: String,
+}
+
+/// 下载一个节点的全部 CC 字幕, 存储为 `{cid}.{lan}.{ext}`, 返回字幕数
+pub async fn download_subtitles(
+    client: &ClientWithMiddleware,
+    dir: &Path,
+    bvid: &VideoId,
+    cid: usize,
+    format: subtitle::Format,
+) -> Result<usize> {
+    let url = format!("https://api.bilibili.com/x/player/v2?bvid={bvid}&cid={cid}");
+    debug!("Fetching subtitle list from `{url}`");
+    let response = client.get(url).send().await?;
+    let tracks = response
+        .json::<Response<PlayerData>>()
+        .await?
+        .data
+        .subtitle
+        .subtitles;
+
+    for Track { language, url } in &tracks {
+        // 字幕 URL 可能省略协议
+        let url = match url.strip_prefix("//") {
+            Some(rest) => format!("https://{rest}"),
+            None => url.clone(),
+        };
+        debug!("Downloading subtitle `{language}` of node {cid} from `{url}`");
+        let subtitle: Subtitle = client.get(url).send().await?.json().await?;
+
+        let path = dir.join(format!("{cid}.{language}.{}", format.extension()));
+        write_file(&path, subtitle.convert(format))?;
+    }
+    Ok(tracks.len())
+}
+
+/// 下载一个节点的弹幕, 返回文件数
+pub async fn download_danmaku(
+    client: &ClientWithMiddleware,
+    dir: &Path,
+    cid: usize,
+    format: Danmaku,
+) -> Result<usize> {
+    match format {
+        Danmaku::Xml => {
+            let url = format!("https://api.bilibili.com/x/v1/dm/list.so?oid={cid}");
+            debug!("Downloading danmaku of node {cid} from `{url}`");
+            let bytes = download_to_bytes(client, &url, |_| ()).await?;
+            write_file(&dir.join(format!("{cid}.danmaku.xml")), bytes)?;
+            Ok(1)
+        }
+        Danmaku::Protobuf => {
+            // 超出视频长度的分段为空
+            for n in 1..=DANMAKU_SEGMENT_MAX {
+                let url = format!(
+                    "https://api.bilibili.com/x/v2/dm/web/seg.so?type=1&oid={cid}&segment_index={n}"
+                );
+                debug!("Downloading danmaku segment {n} of node {cid} from `{url}`");
+                let bytes = client
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?;
+                if bytes.is_empty() {
+                    return Ok(n - 1);
+                }
+                write_file(&dir.join(format!("{cid}.danmaku.{n}.pb")), bytes)?;
+            }
+            Ok(DANMAKU_SEGMENT_MAX)
+        }
+    }
 }
 
 //////// service ////////
@@ -120,45 +534,553 @@ pub enum Error {
     #[error("找不到视频流 URL: `{0}`")]
     StreamNotFound(String), // 携带请求 URL
 
+    #[error("下载被截断: 应为 {expected} 字节, 实际 {actual} 字节")]
+    Truncated { expected: u64, actual: u64 },
+
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+
     #[error(transparent)]
-    Io(#[from] std::io::Error),
+    Io(#[from] io::Error),
+
+    #[error("下载已取消")]
+    Cancelled,
 }
 
 impl Video {
+    /// 并发下载指定节点的视频到 `path/`, 每个节点完成后更新完整性记录
+    #[allow(clippy::too_many_arguments)]
+    async fn download_nodes<P>(
+        &self,
+        client: &ClientWithMiddleware,
+        path: &Path,
+        ids: Vec<usize>,
+        quality: Quality,
+        concurrency: usize,
+        mut progress: P,
+        cancel: &CancelToken,
+    ) -> Result<()>
+    where
+        P: FnMut(Progress),
+    {
+        let bvid = &self.id;
+        fs::create_dir_all(path)?;
+
+        let names: HashMap<usize, &str> = self
+            .graph
+            .nodes
+            .iter()
+            .map(|n| (n.id, n.name.as_str()))
+            .collect();
+        let name = |id: usize| names.get(&id).copied().unwrap_or_default().to_string();
+        let mut checksums = Checksums::load(path)?;
+        let total = ids.len();
+
+        // 各节点的传输进度经由通道汇总
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut transfers: HashMap<usize, Transfer> = HashMap::new();
+
+        // 出错或取消时丢弃其余进行中的下载, 已写入的文件均有记录
+        let mut downloads = stream::iter(ids)
+            .map(|id| {
+                let sender = sender.clone();
+                async move {
+                    let file = path.join(format!("{id}.mp4"));
+                    download(client, &file, bvid, id, quality, |transfer| {
+                        let _ = sender.send((id, transfer));
+                    })
+                    .await
+                    .map(|checksum| (id, checksum))
+                }
+            })
+            .buffer_unordered(concurrency.max(1));
+
+        let mut current = 0;
+        loop {
+            tokio::select! {
+                biased;
+                _ = cancel.cancelled() => return Err(Error::Cancelled),
+                Some((id, transfer)) = receiver.recv() => {
+                    transfers.insert(id, transfer.clone());
+                    progress(Progress::Download {
+                        current,
+                        total,
+                        id,
+                        name: name(id),
+                        transfer,
+                    });
+                }
+                result = downloads.next() => {
+                    let Some(result) = result else {
+                        break;
+                    };
+                    let (id, checksum) = result?;
+                    checksums.0.insert(id, checksum);
+                    checksums.save(path)?;
+
+                    // 完成前发出的传输进度已在通道中
+                    while let Ok((id, transfer)) = receiver.try_recv() {
+                        transfers.insert(id, transfer);
+                    }
+                    current += 1;
+                    progress(Progress::Download {
+                        current,
+                        total,
+                        id,
+                        name: name(id),
+                        transfer: transfers.remove(&id).unwrap_or_default(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 断点续传时检查 `path/` 下指定节点的已有视频, 返回需要 (重新) 下载的节点
+    ///
+    /// 见 [`check_existing`], 补录的记录写入完整性记录文件.
+    async fn resume_nodes(
+        &self,
+        client: &ClientWithMiddleware,
+        path: &Path,
+        ids: Vec<usize>,
+        quality: Quality,
+        concurrency: usize,
+    ) -> Result<Vec<usize>> {
+        let bvid = &self.id;
+        let mut checksums = Checksums::load(path)?;
+
+        let checked: Vec<(usize, Option<Checksum>)> = stream::iter(ids)
+            .map(|id| {
+                let recorded = checksums.0.get(&id);
+                async move {
+                    let file = path.join(format!("{id}.mp4"));
+                    check_existing(client, &file, bvid, id, quality, recorded)
+                        .await
+                        .map(|checksum| (id, checksum))
+                }
+            })
+            .buffered(concurrency.max(1))
+            .try_collect()
+            .await?;
+
+        let mut pending = Vec::new();
+        let mut adopted = 0;
+        for (id, checksum) in checked {
+            let Some(checksum) = checksum else {
+                pending.push(id);
+                continue;
+            };
+            emit(Event::NodeSkipped { id });
+            if checksums.0.insert(id, checksum).is_none() {
+                adopted += 1;
+            }
+        }
+        if adopted > 0 {
+            info!("Recorded checksums of {adopted} existing videos of `{bvid}`");
+            checksums.save(path)?;
+        }
+        Ok(pending)
+    }
+
     /// 下载关联的视频
     pub async fn download<P>(
         &self,
         client: &ClientWithMiddleware,
         path: &Path,
         quality: Quality,
+        progress: P,
+    ) -> Result<()>
+    where
+        P: FnMut(Progress),
+    {
+        self.download_with_cancel(client, path, quality, progress, &CancelToken::new())
+            .await
+    }
+
+    /// 可取消的下载, 取消后丢弃进行中的节点并返回 [`Error::Cancelled`]
+    ///
+    /// 已完成的节点均有完整性记录, 可以通过 [`Video::repair`] 继续.
+    pub async fn download_with_cancel<P>(
+        &self,
+        client: &ClientWithMiddleware,
+        path: &Path,
+        quality: Quality,
+        progress: P,
+        cancel: &CancelToken,
+    ) -> Result<()>
+    where
+        P: FnMut(Progress),
+    {
+        let bvid = &self.id;
+        info!("Start downloading video `{bvid}`");
+
+        // 按照编号存储在 path/ 下
+        let ids = self.graph.nodes.iter().map(|n| n.id).collect();
+        self.download_nodes(client, path, ids, quality, 1, progress, cancel)
+            .await?;
+
+        info!(
+            "Video `{bvid}` fetching done! See at `{}`",
+            path.to_string_lossy()
+        );
+        Ok(())
+    }
+
+    /// 按选项下载到打包目录 `dir`
+    ///
+    /// 节点视频, 字幕和弹幕位于 `dir/video/`, 图片资源位于 `dir/`.
+    /// `progress` 只报告节点视频的下载进度, 取消时返回 [`Error::Cancelled`].
+    pub async fn download_with<P>(
+        &self,
+        client: &ClientWithMiddleware,
+        dir: &Path,
+        options: &DownloadOptions,
+        progress: P,
+        cancel: &CancelToken,
+    ) -> Result<()>
+    where
+        P: FnMut(Progress),
+    {
+        let bvid = &self.id;
+        let path = dir.join(VIDEO_DIR);
+        info!("Start downloading video `{bvid}` with {options:?}");
+
+        let ids = self.graph.nodes.iter().map(|n| n.id).collect();
+        let ids = match options.overwrite {
+            Overwrite::Resume => cancel
+                .run(self.resume_nodes(client, &path, ids, options.quality, options.concurrency))
+                .await
+                .ok_or(Error::Cancelled)??,
+            Overwrite::Replace => ids,
+        };
+        self.download_nodes(
+            client,
+            &path,
+            ids,
+            options.quality,
+            options.concurrency,
+            progress,
+            cancel,
+        )
+        .await?;
+
+        if options.assets {
+            cancel
+                .run(self.download_assets(client, dir))
+                .await
+                .ok_or(Error::Cancelled)??;
+        }
+        cancel
+            .run(self.download_extras(client, &path, options.extras, |_| ()))
+            .await
+            .ok_or(Error::Cancelled)??;
+
+        info!(
+            "Video `{bvid}` fetching done! See at `{}`",
+            dir.to_string_lossy()
+        );
+        Ok(())
+    }
+
+    /// 按完整性记录校验 `path/` 下的节点视频
+    pub fn verify_download(&self, path: &Path) -> Result<Verification> {
+        let checksums = Checksums::load(path)?;
+        let mut report = Verification::default();
+
+        for Node { id, .. } in &self.graph.nodes {
+            let file = path.join(format!("{id}.mp4"));
+            let mut reader = match File::open(&file) {
+                Ok(reader) => reader,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    report.missing.push(*id);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let Some(Checksum { size, sha256: hash }) = checksums.0.get(id) else {
+                report.unrecorded.push(*id);
+                continue;
+            };
+
+            match sha256(&mut reader) {
+                Ok((s, _)) if s < *size => report.truncated.push(*id),
+                Ok((s, h)) if s == *size && h == *hash => {}
+                _ => report.corrupt.push(*id),
+            }
+        }
+
+        info!(
+            "Video `{}` verified, {} missing, {} truncated, {} corrupt, {} unrecorded",
+            self.id,
+            report.missing.len(),
+            report.truncated.len(),
+            report.corrupt.len(),
+            report.unrecorded.len()
+        );
+        Ok(report)
+    }
+
+    /// 校验 `path/` 下的节点视频, 只重新下载缺失, 截断或损坏的节点
+    ///
+    /// 没有记录的节点与服务器的 Content-Length 比较, 一致时补录记录, 否则同样重新下载.
+    /// 返回修复前的校验报告.
+    pub async fn repair<P>(
+        &self,
+        client: &ClientWithMiddleware,
+        path: &Path,
+        quality: Quality,
+        progress: P,
+    ) -> Result<Verification>
+    where
+        P: FnMut(Progress),
+    {
+        self.repair_with_cancel(client, path, quality, progress, &CancelToken::new())
+            .await
+    }
+
+    /// 可取消的修复, 取消后返回 [`Error::Cancelled`], 已修复的节点均有完整性记录
+    pub async fn repair_with_cancel<P>(
+        &self,
+        client: &ClientWithMiddleware,
+        path: &Path,
+        quality: Quality,
+        progress: P,
+        cancel: &CancelToken,
+    ) -> Result<Verification>
+    where
+        P: FnMut(Progress),
+    {
+        let report = self.verify_download(path)?;
+        let mut broken: Vec<usize> = report.broken().collect();
+        broken.extend(
+            cancel
+                .run(self.resume_nodes(client, path, report.unrecorded.clone(), quality, 1))
+                .await
+                .ok_or(Error::Cancelled)??,
+        );
+        if broken.is_empty() {
+            return Ok(report);
+        }
+
+        info!("Repairing {} nodes of video `{}`", broken.len(), self.id);
+        self.download_nodes(client, path, broken, quality, 1, progress, cancel)
+            .await?;
+        Ok(report)
+    }
+
+    /// 下载每个节点的字幕和弹幕, 与节点视频一同存储在 `path/` 下
+    pub async fn download_extras<P>(
+        &self,
+        client: &ClientWithMiddleware,
+        path: &Path,
+        extras: Extras,
         mut progress: P,
     ) -> Result<()>
     where
         P: FnMut(Progress),
     {
+        if extras == Extras::default() {
+            return Ok(());
+        }
+
         let bvid = &self.id;
         fs::create_dir_all(path)?;
-        info!("Start downloading video `{bvid}`");
+        info!("Start downloading subtitles and danmaku of video `{bvid}`");
 
-        // 循环下载每一个节点的视频, 按照编号存储在 path/ 下
         let nodes = &self.graph.nodes;
         let total = nodes.len();
         for (k, Node { id, name, .. }) in nodes.iter().enumerate() {
-            let path = path.join(format!("{id}.mp4"));
-            download(client, &path, bvid, *id, quality).await?;
+            if let Some(format) = extras.subtitle {
+                let count = download_subtitles(client, path, bvid, *id, format).await?;
+                debug!("{count} subtitles of node {id} downloaded");
+            }
+            if let Some(format) = extras.danmaku {
+                let count = download_danmaku(client, path, *id, format).await?;
+                debug!("{count} danmaku files of node {id} downloaded");
+            }
 
-            progress(Progress {
+            progress(Progress::Download {
                 current: k + 1,
                 total,
                 id: *id,
                 name: name.clone(),
+                transfer: Transfer {
+                    done: true,
+                    ..Default::default()
+                },
             });
         }
 
+        info!("Subtitles and danmaku of video `{bvid}` downloaded");
+        Ok(())
+    }
+
+    /// 下载封面, UP 主头像和节点缩略图到下载目录 `dir`
+    ///
+    /// # Notes
+    ///
+    /// - 图片资源可选, 单张图片下载失败只记录警告
+    pub async fn download_assets(
+        &self,
+        client: &ClientWithMiddleware,
+        dir: &Path,
+    ) -> Result<Assets> {
+        info!("Downloading assets of video `{}`", self.id);
+        fs::create_dir_all(dir)?;
+
+        let mut assets = Assets::default();
+        let download = async |stem: &str, url: &str| {
+            if url.is_empty() {
+                return None;
+            }
+            download_image(client, dir, stem, url)
+                .await
+                .inspect_err(|e| {
+                    warn!("Failed to download image `{url}`: {e}");
+                    warning(format!("图片 `{url}` 下载失败: {e}"));
+                })
+                .ok()
+        };
+
+        assets.cover = download(COVER_STEM, &self.cover).await;
+        if let Some(url) = &self.avatar {
+            assets.avatar = download(AVATAR_STEM, url).await;
+        }
+        for node in &self.graph.nodes {
+            let Some(url) = &node.cover else {
+                continue;
+            };
+            if let Some(path) = download(&thumbnail_stem(node.id), url).await {
+                assets.thumbnails.insert(node.id, path);
+            }
+        }
+
         info!(
-            "Video `{bvid}` fetching done! See at `{}`",
-            path.to_string_lossy()
+            "Assets of video `{}` downloaded, {} thumbnails",
+            self.id,
+            assets.thumbnails.len()
         );
-        Ok(())
+        Ok(assets)
+    }
+}
+
+//////// test ////////
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use reqwest_middleware::ClientBuilder;
+    use tempfile::tempdir;
+
+    use super::{
+        Checksum, Checksums, Danmaku, DownloadOptions, Overwrite, Quality, Verification,
+        check_existing, image_extension,
+    };
+
+    use crate::{model::Video, utils::sha256};
+
+    #[test]
+    fn test_verify_download() {
+        let video: Video = serde_json::from_str(
+            r#"{
+            "id": "BV17x411w7KC", "name": "", "cover": "", "description": "", "author": "",
+            "variables": [],
+            "graph": {"root": 1, "nodes": [
+                {"id": 1, "name": "N1", "type": "jump", "choices": []},
+                {"id": 2, "name": "N2", "type": "jump", "choices": []},
+                {"id": 3, "name": "N3", "type": "jump", "choices": []},
+                {"id": 4, "name": "N4", "type": "jump", "choices": []},
+                {"id": 5, "name": "N5", "type": "leaf"}
+            ]}
+        }"#,
+        )
+        .unwrap();
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+
+        let mut checksums = Checksums::default();
+        for id in 1..=4 {
+            let content = format!("node {id} content");
+            fs::write(path.join(format!("{id}.mp4")), &content).unwrap();
+            let (size, sha256) = sha256(&mut content.as_bytes()).unwrap();
+            checksums.0.insert(id, Checksum { size, sha256 });
+        }
+        checksums.save(path).unwrap();
+        fs::write(path.join("5.mp4"), "node 5").unwrap();
+
+        fs::remove_file(path.join("1.mp4")).unwrap();
+        fs::write(path.join("2.mp4"), "node 2").unwrap();
+        fs::write(path.join("3.mp4"), "node 3 CONTENT").unwrap();
+
+        let report = video.verify_download(path).unwrap();
+        assert_eq!(
+            report,
+            Verification {
+                missing: vec![1],
+                truncated: vec![2],
+                corrupt: vec![3],
+                unrecorded: vec![5],
+            }
+        );
+        assert_eq!(report.broken().collect::<Vec<_>>(), [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_check_existing() {
+        let client = ClientBuilder::new(reqwest::Client::new()).build();
+        let bvid = "BV17x411w7KC".parse().unwrap();
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("1.mp4");
+
+        let check = async |recorded: Option<&Checksum>| {
+            check_existing(&client, &file, &bvid, 1, Quality::High, recorded)
+                .await
+                .unwrap()
+        };
+
+        // 缺失的文件不发出请求
+        let (size, sha256) = sha256(&mut "node 1 content".as_bytes()).unwrap();
+        let checksum = Checksum { size, sha256 };
+        assert_eq!(check(None).await, None);
+        assert_eq!(check(Some(&checksum)).await, None);
+
+        fs::write(&file, "node 1 content").unwrap();
+        assert_eq!(check(Some(&checksum)).await, Some(checksum.clone()));
+
+        // 大小一致但内容不同
+        fs::write(&file, "node 1 CONTENT").unwrap();
+        assert_eq!(check(Some(&checksum)).await, None);
+        fs::write(&file, "node 1").unwrap();
+        assert_eq!(check(Some(&checksum)).await, None);
+    }
+
+    #[test]
+    fn test_download_options() {
+        // 缺失的字段取默认值
+        let options: DownloadOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options, DownloadOptions::default());
+
+        let options: DownloadOptions = serde_json::from_str(
+            r#"{"quality": "very_low", "overwrite": "replace", "extras": {"danmaku": "xml"}}"#,
+        )
+        .unwrap();
+        assert_eq!(options.quality, Quality::VeryLow);
+        assert_eq!(options.overwrite, Overwrite::Replace);
+        assert_eq!(options.extras.danmaku, Some(Danmaku::Xml));
+        assert_eq!(options.extras.subtitle, None);
+    }
+
+    #[test]
+    fn test_image_extension() {
+        assert_eq!(image_extension(Some("image/png"), "https://a/b.jpg"), "png");
+        assert_eq!(image_extension(Some("image/webp; q=1"), ""), "webp");
+        assert_eq!(
+            image_extension(Some("application/octet-stream"), "https://a/b.JPEG?x=1"),
+            "jpg"
+        );
+        assert_eq!(image_extension(None, "https://a/b.gif"), "gif");
+        assert_eq!(image_extension(None, "https://a/b"), "jpg");
     }
 }
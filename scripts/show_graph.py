@@ -5,6 +5,7 @@ def generate(json_file: str) -> None:
     """读取 JSON 文件并生成 Mermaid 流程图，保存为同名的 .md 文件。"""
     with open(json_file, 'r', encoding='utf-8') as f:
         data: Dict[str, Any] = json.load(f)
+    data = data.get('video', data)  # 兼容带信封的新格式
 
     nodes: List[Dict[str, Any]] = data['graph']['nodes']
     node_names: Dict[int, str] = {node['id']: node['name'] for node in nodes}